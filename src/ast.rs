@@ -0,0 +1,359 @@
+use std::fmt;
+
+use crate::parser::{is_meta_character, CLASS_RANGE};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ast {
+    Empty,
+    Literal(char),
+    Concat(Vec<Ast>),
+    Alternation(Vec<Ast>),
+    // ? `max` of None is unbounded, a star is min 0 and no max. A lazy
+    // ? (non-greedy) repetition prefers running as few times as it can.
+    Repetition {
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+        ast: Box<Ast>,
+    },
+    Group {
+        index: usize,
+        name: Option<String>,
+        ast: Box<Ast>,
+    },
+    Class(Vec<ClassRange>),
+    NegatedClass(Vec<ClassRange>),
+    Anchor(Anchor),
+}
+
+// ? inclusive on both ends, a single character is a range with start == end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClassRange {
+    pub start: char,
+    pub end: char,
+}
+
+// ? `^` and `\A` mean the same without a multi-line mode, they are kept apart
+// ? so the pattern prints back the way it was written. The ASCII word boundaries
+// ? are what `\b` and `\B` parse to with Unicode off and print the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    End,
+    StartText,
+    EndText,
+    WordBoundary,
+    NotWordBoundary,
+    AsciiWordBoundary,
+    AsciiNotWordBoundary,
+}
+
+impl ClassRange {
+    pub fn new(start: char, end: char) -> ClassRange {
+        ClassRange { start, end }
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> {
+        self.start..=self.end
+    }
+}
+
+impl Ast {
+    pub fn literal_str(string: &str) -> Ast {
+        let mut literals: Vec<Ast> = string.chars().map(Ast::Literal).collect();
+
+        match literals.len() {
+            0 => Ast::Empty,
+            1 => literals.remove(0),
+            _ => Ast::Concat(literals),
+        }
+    }
+
+    pub fn concat(mut array_of_ast: Vec<Ast>) -> Ast {
+        match array_of_ast.len() {
+            0 => Ast::Empty,
+            1 => array_of_ast.remove(0),
+            _ => Ast::Concat(array_of_ast),
+        }
+    }
+
+    pub fn alternation(mut array_of_ast: Vec<Ast>) -> Ast {
+        match array_of_ast.len() {
+            0 => Ast::Empty,
+            1 => array_of_ast.remove(0),
+            _ => Ast::Alternation(array_of_ast),
+        }
+    }
+
+    pub fn repetition(ast: Ast) -> Ast {
+        Ast::repeat(ast, 0, None)
+    }
+
+    pub fn repeat(ast: Ast, min: u32, max: Option<u32>) -> Ast {
+        Ast::Repetition {
+            min,
+            max,
+            greedy: true,
+            ast: Box::new(ast),
+        }
+    }
+
+    pub fn lazy_repeat(ast: Ast, min: u32, max: Option<u32>) -> Ast {
+        Ast::Repetition {
+            min,
+            max,
+            greedy: false,
+            ast: Box::new(ast),
+        }
+    }
+
+    // ? capture groups are numbered from 1 by their opening '(', 0 is the whole match
+    pub fn group(index: usize, ast: Ast) -> Ast {
+        Ast::Group {
+            index,
+            name: None,
+            ast: Box::new(ast),
+        }
+    }
+
+    pub fn named_group(index: usize, name: &str, ast: Ast) -> Ast {
+        Ast::Group {
+            index,
+            name: Some(name.to_string()),
+            ast: Box::new(ast),
+        }
+    }
+
+    pub fn group_count(&self) -> usize {
+        match self {
+            Ast::Concat(array_of_ast) | Ast::Alternation(array_of_ast) => {
+                array_of_ast.iter().map(Ast::group_count).sum()
+            }
+            Ast::Repetition { ast, .. } => ast.group_count(),
+            Ast::Group { ast, .. } => 1 + ast.group_count(),
+            _ => 0,
+        }
+    }
+
+    // ? names indexed by group number, entry 0 (the whole match) is always None
+    pub fn group_names(&self) -> Vec<Option<String>> {
+        let mut names: Vec<Option<String>> = vec![None; self.group_count() + 1];
+        self.collect_group_names(&mut names);
+        names
+    }
+
+    fn collect_group_names(&self, names: &mut [Option<String>]) {
+        match self {
+            Ast::Concat(array_of_ast) | Ast::Alternation(array_of_ast) => {
+                for ast in array_of_ast {
+                    ast.collect_group_names(names);
+                }
+            }
+            Ast::Repetition { ast, .. } => ast.collect_group_names(names),
+            Ast::Group { index, name, ast } => {
+                names[*index].clone_from(name);
+                ast.collect_group_names(names);
+            }
+            _ => {}
+        }
+    }
+
+    // ? binding strength, used to decide where the printer needs parentheses
+    fn precedence(&self) -> u8 {
+        match self {
+            Ast::Alternation(_) => 0,
+            Ast::Concat(_) => 1,
+            Ast::Repetition { .. } => 2,
+            _ => 3,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, min_precedence: u8) -> fmt::Result {
+        if self.precedence() < min_precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+fn fmt_char(f: &mut fmt::Formatter<'_>, symbol: char) -> fmt::Result {
+    if is_meta_character(symbol) {
+        write!(f, "\\{}", symbol)
+    } else {
+        write!(f, "{}", symbol)
+    }
+}
+
+fn fmt_class_char(f: &mut fmt::Formatter<'_>, symbol: char) -> fmt::Result {
+    if symbol == CLASS_RANGE || is_meta_character(symbol) {
+        write!(f, "\\{}", symbol)
+    } else {
+        write!(f, "{}", symbol)
+    }
+}
+
+fn fmt_class_ranges(f: &mut fmt::Formatter<'_>, ranges: &[ClassRange]) -> fmt::Result {
+    for range in ranges {
+        fmt_class_char(f, range.start)?;
+        if range.end != range.start {
+            write!(f, "-")?;
+            fmt_class_char(f, range.end)?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ast::Empty => Ok(()),
+            Ast::Literal(symbol) => fmt_char(f, *symbol),
+            Ast::Concat(array_of_ast) => {
+                for ast in array_of_ast {
+                    write!(f, "{}", ast)?;
+                }
+                Ok(())
+            }
+            Ast::Alternation(array_of_ast) => {
+                for (index, ast) in array_of_ast.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{}", ast)?;
+                }
+                Ok(())
+            }
+            Ast::Repetition {
+                min,
+                max,
+                greedy,
+                ast,
+            } => {
+                ast.fmt_operand(f, 2)?;
+                match (min, max) {
+                    (0, None) => write!(f, "*")?,
+                    (1, None) => write!(f, "+")?,
+                    // ? a '?' straight after another quantifier would make it lazy
+                    (0, Some(1)) if matches!(**ast, Ast::Repetition { .. }) => {
+                        write!(f, "{{0,1}}")?
+                    }
+                    (0, Some(1)) => write!(f, "?")?,
+                    (min, None) => write!(f, "{{{},}}", min)?,
+                    (min, Some(max)) if min == max => write!(f, "{{{}}}", min)?,
+                    (min, Some(max)) => write!(f, "{{{},{}}}", min, max)?,
+                }
+                if !greedy {
+                    write!(f, "?")?;
+                }
+                Ok(())
+            }
+            Ast::Group {
+                name: Some(name),
+                ast,
+                ..
+            } => write!(f, "(?<{}>{})", name, ast),
+            Ast::Group { ast, .. } => write!(f, "({})", ast),
+            Ast::Class(ranges) => {
+                write!(f, "[")?;
+                fmt_class_ranges(f, ranges)?;
+                write!(f, "]")
+            }
+            Ast::NegatedClass(ranges) => {
+                write!(f, "[^")?;
+                fmt_class_ranges(f, ranges)?;
+                write!(f, "]")
+            }
+            Ast::Anchor(Anchor::Start) => write!(f, "^"),
+            Ast::Anchor(Anchor::End) => write!(f, "$"),
+            Ast::Anchor(Anchor::StartText) => write!(f, "\\A"),
+            Ast::Anchor(Anchor::EndText) => write!(f, "\\z"),
+            Ast::Anchor(Anchor::WordBoundary | Anchor::AsciiWordBoundary) => write!(f, "\\b"),
+            Ast::Anchor(Anchor::NotWordBoundary | Anchor::AsciiNotWordBoundary) => {
+                write!(f, "\\B")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_constructors_collapse_single_item() {
+        assert_eq!(Ast::concat(vec![Ast::Literal('a')]), Ast::Literal('a'));
+        assert_eq!(Ast::alternation(vec![Ast::Literal('a')]), Ast::Literal('a'));
+        assert_eq!(Ast::concat(vec![]), Ast::Empty);
+        assert_eq!(
+            Ast::literal_str("ab"),
+            Ast::Concat(vec![Ast::Literal('a'), Ast::Literal('b')])
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let ast = Ast::concat(vec![
+            Ast::repetition(Ast::group(
+                1,
+                Ast::alternation(vec![Ast::Literal('a'), Ast::Literal('b')]),
+            )),
+            Ast::literal_str("abb"),
+        ]);
+        assert_eq!(ast.to_string(), "(a|b)*abb");
+        assert_eq!(ast.group_count(), 1);
+
+        let ast = Ast::repetition(Ast::literal_str("ab"));
+        assert_eq!(ast.to_string(), "(ab)*");
+
+        let ast = Ast::Class(vec![ClassRange::new('a', 'z'), ClassRange::new('_', '_')]);
+        assert_eq!(ast.to_string(), "[a-z_]");
+
+        assert_eq!(Ast::Literal('*').to_string(), "\\*");
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for pattern in [
+            "(a|b)*abb",
+            "a(b|cd)*e",
+            "^[a-c0-9]*$",
+            "a\\*\\|b",
+            "ab|cd",
+            "(?<year>[0-9]*)-(a)",
+            "(\\?)",
+            "[^a-z\\^]x",
+            "a+b?(cd){2}e{2,}f{0,3}",
+            "a\\{\\}",
+            "<a*?b+?c??d{2,}?e{1,3}?>",
+            "a*?{0,1}b+{0,1}",
+            "\\Aab$|^c\\z",
+            "\\ba\\B[b\\-]*\\b",
+        ] {
+            let ast = Parser::parse(pattern).unwrap();
+            assert_eq!(ast.to_string(), pattern);
+            assert_eq!(Parser::parse(&ast.to_string()).unwrap(), ast);
+        }
+    }
+
+    #[test]
+    fn test_group_names() {
+        let ast = Ast::concat(vec![
+            Ast::named_group(1, "first", Ast::Literal('a')),
+            Ast::group(2, Ast::named_group(3, "inner", Ast::Literal('b'))),
+        ]);
+
+        assert_eq!(
+            ast.group_names(),
+            vec![
+                None,
+                Some("first".to_string()),
+                None,
+                Some("inner".to_string())
+            ]
+        );
+        assert_eq!(Ast::Literal('a').group_names(), vec![None]);
+    }
+}
@@ -0,0 +1,249 @@
+use std::ops::{Index, Range};
+use std::sync::Arc;
+
+// ? a matched substring, start and end are byte offsets into the haystack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    pub fn new(haystack: &'h str, start: usize, end: usize) -> Match<'h> {
+        Match {
+            haystack,
+            start,
+            end,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.range()]
+    }
+}
+
+// ? Positions recorded for every capture group, group 0 is the whole match.
+// ? A group that did not take part in the match (e.g. the other side of an
+// ? alternation) has no positions. `names` is shared with the compiled NFA.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl<'h> Captures<'h> {
+    pub fn new(
+        haystack: &'h str,
+        slots: Vec<Option<usize>>,
+        names: Arc<[Option<String>]>,
+    ) -> Captures<'h> {
+        Captures {
+            haystack,
+            slots,
+            names,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(index * 2)?)?;
+        let end = (*self.slots.get(index * 2 + 1)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    // ? Appends `template` to `dst` with group references filled in:
+    // ?   $1 / ${1}         group by number, $12 is group 12
+    // ?   $name / ${name}   group by name, a name is letters, digits and '_'
+    // ?   $$                a literal '$'
+    // ? A group that doesn't exist or didn't match expands to nothing, and a '$'
+    // ? that starts none of the above is kept as is.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            dst.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (reference, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(close) => (&braced[..close], &braced[close + 1..]),
+                    None => ("", rest),
+                },
+                None if rest.starts_with(|symbol: char| symbol.is_ascii_digit()) => {
+                    let len = rest
+                        .find(|symbol: char| !symbol.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    rest.split_at(len)
+                }
+                None => {
+                    let len = rest
+                        .find(|symbol: char| !(symbol.is_ascii_alphanumeric() || symbol == '_'))
+                        .unwrap_or(rest.len());
+                    rest.split_at(len)
+                }
+            };
+
+            if reference.is_empty() {
+                dst.push('$');
+                continue;
+            }
+
+            let group = match reference.parse::<usize>() {
+                Ok(index) => self.get(index),
+                Err(_) => self.name(reference),
+            };
+            if let Some(group) = group {
+                dst.push_str(group.as_str());
+            }
+            rest = after;
+        }
+
+        dst.push_str(rest);
+    }
+
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let index = self
+            .names
+            .iter()
+            .position(|other| other.as_deref() == Some(name))?;
+        self.get(index)
+    }
+
+    // ? number of groups in the pattern, including group 0
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+}
+
+impl<'h> Index<usize> for Captures<'h> {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        match self.get(index) {
+            Some(group) => group.as_str(),
+            None => panic!("no group at index {}", index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_match() {
+        let found = Match::new("xxabcxx", 2, 5);
+
+        assert_eq!(found.start(), 2);
+        assert_eq!(found.end(), 5);
+        assert_eq!(found.range(), 2..5);
+        assert_eq!(found.as_str(), "abc");
+        assert!(!found.is_empty());
+        assert!(Match::new("abc", 1, 1).is_empty());
+    }
+
+    #[test]
+    fn test_captures_get_and_index() {
+        let caps = Captures::new(
+            "ab",
+            vec![Some(0), Some(2), Some(1), Some(2), None, None],
+            Arc::from([]),
+        );
+
+        assert_eq!(caps.len(), 3);
+        assert_eq!(&caps[0], "ab");
+        assert_eq!(&caps[1], "b");
+        assert_eq!(caps.get(1).map(|group| group.range()), Some(1..2));
+        assert_eq!(caps.get(2), None);
+        assert_eq!(caps.get(3), None);
+        assert_eq!(caps.iter().filter(Option::is_some).count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "no group at index 2")]
+    fn test_captures_index_missing_group() {
+        let caps = Captures::new(
+            "ab",
+            vec![Some(0), Some(2), None, None, None, None],
+            Arc::from([]),
+        );
+
+        let _ = &caps[2];
+    }
+
+    fn expand(template: &str) -> String {
+        let names: Arc<[Option<String>]> = Arc::from([None, None, Some("word".to_string())]);
+        let caps = Captures::new(
+            "ab cd",
+            vec![Some(0), Some(5), Some(0), Some(2), Some(3), Some(5)],
+            names,
+        );
+
+        let mut dst = String::new();
+        caps.expand(template, &mut dst);
+        dst
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("$2-$1"), "cd-ab");
+        assert_eq!(expand("${word}_${1}x"), "cd_abx");
+        assert_eq!(expand("$word $0"), "cd ab cd");
+        assert_eq!(expand("$1x"), "abx");
+        assert_eq!(expand("$$1 costs $"), "$1 costs $");
+    }
+
+    #[test]
+    fn test_expand_missing_groups() {
+        assert_eq!(expand("[$7][${nope}][$nope]"), "[][][]");
+        assert_eq!(expand("${1"), "${1");
+        assert_eq!(expand("$-"), "$-");
+    }
+
+    #[test]
+    fn test_captures_name() {
+        let names: Arc<[Option<String>]> =
+            Arc::from([None, Some("year".to_string()), Some("day".to_string())]);
+        let caps = Captures::new(
+            "2024",
+            vec![Some(0), Some(4), Some(0), Some(4), None, None],
+            names,
+        );
+
+        assert_eq!(caps.name("year").map(|group| group.as_str()), Some("2024"));
+        assert_eq!(caps.name("day"), None);
+        assert_eq!(caps.name("month"), None);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::ast::{Anchor, Ast, ClassRange};
+use crate::look::Look;
+use crate::nfa::NFA;
+use crate::state::CharRange;
+
+// ? Thompson construction: every AST node lowers to an NFA fragment built with
+// ? the combinators in nfa.rs, children first.
+pub struct Compiler;
+
+impl Compiler {
+    // ? the whole pattern is wrapped in capture group 0
+    pub fn compile(ast: &Ast) -> NFA {
+        Compiler::compile_with_limit(ast, usize::MAX).expect("no size limit")
+    }
+
+    // ? None once the NFA would have more than `size_limit` states. Counted
+    // ? repetitions are checked against an upper bound of their size before they
+    // ? are expanded, so `(a{1000}){1000}` fails fast instead of building a
+    // ? million states first.
+    pub fn compile_with_limit(ast: &Ast, size_limit: usize) -> Option<NFA> {
+        let mut nfa = NFA::group(Compiler::lower(ast, size_limit)?, 0);
+        if nfa.states.len() > size_limit {
+            return None;
+        }
+        nfa.group_names = ast.group_names().into();
+        Some(nfa)
+    }
+
+    fn empty() -> NFA {
+        let mut nfa = NFA::new();
+        nfa.add_epsilon_transition();
+        nfa
+    }
+
+    // ? overlapping or touching ranges are merged so each char has a single edge
+    fn char_ranges(ranges: &[ClassRange]) -> Vec<CharRange> {
+        let ranges: Vec<CharRange> = ranges
+            .iter()
+            .map(|range| CharRange::new(range.start, range.end))
+            .collect();
+        CharRange::canonicalize(&ranges)
+    }
+
+    fn lower(ast: &Ast, size_limit: usize) -> Option<NFA> {
+        let nfa = match ast {
+            // ? an empty Concat or Alternation can only be built by hand, it matches
+            // ? the empty string like Empty does
+            Ast::Empty => Compiler::empty(),
+            Ast::Concat(array_of_ast) | Ast::Alternation(array_of_ast)
+                if array_of_ast.is_empty() =>
+            {
+                Compiler::empty()
+            }
+            Ast::Anchor(Anchor::Start | Anchor::StartText) => NFA::look(Look::Start),
+            Ast::Anchor(Anchor::End | Anchor::EndText) => NFA::look(Look::End),
+            Ast::Anchor(Anchor::WordBoundary) => NFA::look(Look::WordBoundary),
+            Ast::Anchor(Anchor::NotWordBoundary) => NFA::look(Look::NotWordBoundary),
+            Ast::Anchor(Anchor::AsciiWordBoundary) => NFA::look(Look::AsciiWordBoundary),
+            Ast::Anchor(Anchor::AsciiNotWordBoundary) => NFA::look(Look::AsciiNotWordBoundary),
+            Ast::Literal(symbol) => NFA::char(*symbol),
+            Ast::Concat(array_of_ast) => {
+                let mut array_of_nfa = array_of_ast
+                    .iter()
+                    .map(|ast| Compiler::lower(ast, size_limit))
+                    .collect::<Option<Vec<NFA>>>()?;
+                let first = array_of_nfa.remove(0);
+                NFA::concat(first, array_of_nfa)
+            }
+            Ast::Alternation(array_of_ast) => {
+                let mut array_of_nfa = array_of_ast
+                    .iter()
+                    .map(|ast| Compiler::lower(ast, size_limit));
+                let mut current_nfa = array_of_nfa.next().expect("checked above")?;
+                for next_nfa in array_of_nfa {
+                    current_nfa = NFA::or_pair(current_nfa, next_nfa?);
+                }
+                current_nfa
+            }
+            Ast::Repetition {
+                min,
+                max,
+                greedy,
+                ast,
+            } => {
+                let nfa = Compiler::lower(ast, size_limit)?;
+                // ? every copy is wrapped in at most one extra pair of states
+                let copies = max.unwrap_or(*min).max(1) as usize;
+                if (nfa.states.len() + 2).saturating_mul(copies) > size_limit {
+                    return None;
+                }
+                NFA::repeat(nfa, *min as usize, max.map(|max| max as usize), *greedy)
+            }
+            Ast::Group { index, ast, .. } => NFA::group(Compiler::lower(ast, size_limit)?, *index),
+            Ast::Class(ranges) => NFA::class(&Compiler::char_ranges(ranges)),
+            Ast::NegatedClass(ranges) => {
+                NFA::class(&CharRange::complement(&Compiler::char_ranges(ranges)))
+            }
+        };
+
+        (nfa.states.len() <= size_limit).then_some(nfa)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::Parser;
+
+    fn compile(pattern: &str) -> NFA {
+        Compiler::compile(&Parser::parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn test_compile_concat() {
+        let nfa = compile("abc");
+
+        assert!(nfa.test("abc"));
+        assert!(!nfa.test("ab"));
+        assert!(!nfa.test("abcd"));
+        assert!(!nfa.test(""));
+    }
+
+    #[test]
+    fn test_compile_alternation() {
+        let nfa = compile("ab|cd|e");
+
+        assert!(nfa.test("ab"));
+        assert!(nfa.test("cd"));
+        assert!(nfa.test("e"));
+        assert!(!nfa.test("abcd"));
+        assert!(!nfa.test("b"));
+    }
+
+    #[test]
+    fn test_compile_repetition() {
+        let nfa = compile("ab*");
+
+        assert!(nfa.test("a"));
+        assert!(nfa.test("ab"));
+        assert!(nfa.test("abbbb"));
+        assert!(!nfa.test("abab"));
+        assert!(!nfa.test("b"));
+    }
+
+    #[test]
+    fn test_compile_quantifiers() {
+        let nfa = compile("ab+c?");
+        assert!(nfa.test("ab"));
+        assert!(nfa.test("abbbc"));
+        assert!(!nfa.test("a"));
+        assert!(!nfa.test("abcc"));
+
+        let nfa = compile("(ab){2,3}");
+        assert!(!nfa.test("ab"));
+        assert!(nfa.test("abab"));
+        assert!(nfa.test("ababab"));
+        assert!(!nfa.test("abababab"));
+
+        let nfa = compile("a{2}b{1,}c{0}");
+        assert!(nfa.test("aab"));
+        assert!(nfa.test("aabbb"));
+        assert!(!nfa.test("ab"));
+        assert!(!nfa.test("aac"));
+    }
+
+    #[test]
+    fn test_compile_long_optional_tail() {
+        // ? the optional copies are appended, not nested, so this stays linear
+        let nfa = compile("a{0,5000}");
+        assert_eq!(nfa.states.len(), 2 * 5000 + 4);
+        assert!(nfa.test(""));
+        assert!(nfa.test("aaa"));
+
+        let nfa = compile("a{2,4}?");
+        assert!(!nfa.test("a"));
+        assert!(nfa.test("aaaa"));
+        assert!(!nfa.test("aaaaa"));
+    }
+
+    #[test]
+    fn test_compile_with_limit() {
+        let ast = Parser::parse("(a{10}){10}").unwrap();
+
+        assert!(Compiler::compile_with_limit(&ast, 100).is_none());
+        assert!(Compiler::compile_with_limit(&ast, 1000).is_some());
+
+        // ? the repetition is refused before the outer copies are made
+        let ast = Parser::parse("((a{1000}){1000}){1000}").unwrap();
+        assert!(Compiler::compile_with_limit(&ast, 10_000).is_none());
+    }
+
+    #[test]
+    fn test_compile_group() {
+        let nfa = compile("(a|b)*abb");
+
+        assert!(nfa.test("abb"));
+        assert!(nfa.test("aabb"));
+        assert!(nfa.test("babaabb"));
+        assert!(!nfa.test("ab"));
+        assert!(!nfa.test("abba"));
+
+        let nested = compile("a(b(c|d))*");
+        assert!(nested.test("a"));
+        assert!(nested.test("abcbd"));
+        assert!(!nested.test("abcb"));
+    }
+
+    #[test]
+    fn test_compile_class() {
+        let nfa = compile("[a-c_]*");
+
+        assert!(nfa.test(""));
+        assert!(nfa.test("ab_c"));
+        assert!(!nfa.test("abd"));
+
+        let nfa = compile("[a-zc-f0-9]");
+        let range_count: usize = nfa.states.iter().map(|state| state.transitions.len()).sum();
+        assert_eq!(range_count, 2);
+        assert!(nfa.test("d"));
+        assert!(nfa.test("7"));
+    }
+
+    #[test]
+    fn test_compile_negated_class() {
+        let nfa = compile("[^a-c\\]]x");
+
+        assert!(nfa.test("dx"));
+        assert!(nfa.test("éx"));
+        assert!(nfa.test("\nx"));
+        assert!(!nfa.test("bx"));
+        assert!(!nfa.test("]x"));
+        assert!(!nfa.test("x"));
+    }
+
+    #[test]
+    fn test_compile_empty_and_anchor() {
+        assert!(compile("").test(""));
+        assert!(!compile("").test("a"));
+
+        let nfa = compile("^ab$");
+        assert!(nfa.test("ab"));
+        assert!(!nfa.test("abb"));
+
+        // ? anchors only hold at the ends, not anywhere a char boundary is
+        assert!(!compile("a^b").test("ab"));
+        assert!(!compile("a$b").test("ab"));
+        assert!(compile("\\A(a$|ab)\\z").test("ab"));
+        assert!(compile("a*^$").test(""));
+        assert!(compile("\\ba\\b").test("a"));
+        assert!(!compile("a\\bb").test("ab"));
+        assert!(compile("a\\B[b-]").test("ab") && !compile("a\\B[b-]").test("a-"));
+    }
+
+    #[test]
+    fn test_compile_empty_sequences() {
+        // ? built by hand, the parser never gives an empty Concat or Alternation
+        for ast in [Ast::Concat(vec![]), Ast::Alternation(vec![])] {
+            let nfa = Compiler::compile(&ast);
+            assert!(nfa.test(""));
+            assert!(!nfa.test("a"));
+        }
+
+        let ast = Ast::Concat(vec![Ast::Literal('a'), Ast::Alternation(vec![])]);
+        assert!(Compiler::compile(&ast).test("a"));
+    }
+
+    #[test]
+    fn test_compile_groups() {
+        let nfa = compile("(a)(b(c))|d");
+
+        assert_eq!(nfa.group_count(), 4);
+        assert!(nfa.test("abc"));
+        assert!(nfa.test("d"));
+    }
+
+    #[test]
+    fn test_compile_capture_names() {
+        let nfa = compile("(?<year>a)(b)((?P<day>c))");
+
+        assert_eq!(
+            nfa.capture_names().collect::<Vec<_>>(),
+            vec![None, Some("year"), None, None, Some("day")]
+        );
+        assert_eq!(compile("a").capture_names().collect::<Vec<_>>(), vec![None]);
+
+        // ? `{0}` drops the group's states, but not the group
+        let nfa = compile("(a)(?<x>b){0}");
+        assert_eq!(nfa.group_count(), 3);
+        assert_eq!(
+            nfa.capture_names().collect::<Vec<_>>(),
+            vec![None, None, Some("x")]
+        );
+    }
+}
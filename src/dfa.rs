@@ -1,42 +1,409 @@
 use crate::{
-    nfa::{self, CELL, NFA},
-    state::{State, EPSILON},
+    look::{Look, Neighbour},
+    nfa::{NfaTable, CELL, NFA},
+    search::Anchored,
+    state::{CharRange, StateId, Symbol},
 };
-use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
-use uuid::Uuid;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+// ? DFA state (a sorted set of NFA state ids) -> one cell per input symbol
+pub type DfaTable = BTreeMap<Vec<StateId>, Vec<CELL>>;
 
-#[derive(Clone, Debug)]
+// ? stands for the implicit `.*?` prefix of an unanchored search in the NFA
+// ? state lists of `from_nfa_leftmost_first`, it is never a real arena index
+const PREFIX_LOOP: StateId = StateId(u32::MAX);
+
+// ? States are numbered from 0 (the start state). Transitions are labelled with
+// ? ranges that never overlap, across the whole DFA. A char no range of a state
+// ? covers leads to the implicit dead state.
+// ? Assertions see the chars around a position, so a search can't always start
+// ? in state 0 or accept as soon as it reaches an accepting state:
+// ? `start_states` has the start state for each kind of char the search may
+// ? start after, `start_state` is the one for the start of the haystack, and
+// ? `look_accepting_states` accept only when the next char is one of the listed
+// ? kinds. `accepting_states` accept whatever comes next.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DFA {
-    pub in_state: Rc<RefCell<State>>,
-    pub out_state: Rc<RefCell<State>>,
+    pub start_state: usize,
+    pub start_states: Vec<usize>,
+    pub transitions: Vec<BTreeMap<CharRange, usize>>,
+    pub accepting_states: HashSet<usize>,
+    pub look_accepting_states: HashMap<usize, Vec<Neighbour>>,
 }
 
+// ? a DFA state while it is being built: the NFA states reached so far, with
+// ? the assertions not followed yet, and what the char read last looks like
+type PowersetKey = (Vec<StateId>, Neighbour);
+
 impl DFA {
-    pub fn new() -> DFA {
+    // ? Powerset construction straight over the NFA arena, numbered breadth first.
+    // ? Each DFA state is the sorted ε-closure of a set of NFA states.
+    pub fn from_nfa(nfa: &NFA) -> DFA {
+        DFA::from_nfa_with_limit(nfa, usize::MAX).expect("no size limit")
+    }
+
+    // ? None once the DFA's table would have more than `size_limit` cells, one per
+    // ? state and alphabet piece. A few NFA states can need exponentially many DFA
+    // ? states, `(a|b)*a(a|b){14}` has to remember the last 15 chars, so the
+    // ? construction gives up instead.
+    pub fn from_nfa_with_limit(nfa: &NFA, size_limit: usize) -> Option<DFA> {
+        let alphabet = DFA::get_nfa_alphabet(nfa, Anchored::Yes);
+        let start = DFA::closure(nfa, &[nfa.in_state], &|_| false);
+
+        DFA::powerset(
+            nfa,
+            &alphabet,
+            start,
+            |states, holds| DFA::closure(nfa, states, holds),
+            |states| states.iter().any(|state| nfa.state(*state).accepting),
+            |states, symbol| {
+                let moved: Vec<StateId> = states
+                    .iter()
+                    .flat_map(|state| nfa.state(*state).get_transitions_for_char(symbol))
+                    .copied()
+                    .collect();
+                DFA::closure(nfa, &moved, &|_| false)
+            },
+            size_limit,
+        )
+    }
+
+    // ? The breadth first numbering both constructions share. `resolve` follows
+    // ? the assertions that hold once the chars on both sides are known, `is_match`
+    // ? says if resolved states accept and `step` moves them on a char and takes
+    // ? the ε-closure again. Unless an assertion looks behind, the char read last
+    // ? is left out of the key, and without assertions `resolve` is skipped, so a
+    // ? pattern without them gets exactly the states it always had.
+    fn powerset(
+        nfa: &NFA,
+        alphabet: &[CharRange],
+        start: Vec<StateId>,
+        resolve: impl Fn(&[StateId], &dyn Fn(Look) -> bool) -> Vec<StateId>,
+        is_match: impl Fn(&[StateId]) -> bool,
+        step: impl Fn(&[StateId], char) -> Vec<StateId>,
+        size_limit: usize,
+    ) -> Option<DFA> {
+        let looks: Vec<Look> = nfa
+            .states
+            .iter()
+            .flat_map(|state| state.look_transitions.iter().map(|(look, _)| *look))
+            .collect();
+        let looks_behind = looks.iter().any(|look| look.looks_behind());
+        let remembered = |before: Neighbour| {
+            if looks_behind {
+                before
+            } else {
+                Neighbour::Edge
+            }
+        };
+
+        let mut ids: HashMap<PowersetKey, usize> = HashMap::new();
+        let mut keys: Vec<PowersetKey> = Vec::new();
+        let mut transitions: Vec<BTreeMap<CharRange, usize>> = Vec::new();
+        let mut accepting_states: HashSet<usize> = HashSet::new();
+        let mut look_accepting_states: HashMap<usize, Vec<Neighbour>> = HashMap::new();
+
+        let start_states: Vec<usize> = Neighbour::ALL
+            .iter()
+            .map(|before| DFA::get_id(&mut ids, &mut keys, (start.clone(), remembered(*before))))
+            .collect();
+
+        while let Some((curr_states, before)) = keys.get(transitions.len()).cloned() {
+            // ? every new state is visited, so checking here sees all of them. Each
+            // ? costs a step per alphabet piece, which is what the limit counts.
+            if keys.len().saturating_mul(alphabet.len()) > size_limit {
+                return None;
+            }
+            let id = transitions.len();
+
+            // ? one list per kind of char that may come next
+            let resolved: Vec<Vec<StateId>> = Neighbour::ALL
+                .iter()
+                .map(|after| {
+                    if looks.is_empty() {
+                        curr_states.clone()
+                    } else {
+                        resolve(&curr_states, &|look| look.is_match(before, *after))
+                    }
+                })
+                .collect();
+
+            let accepted: Vec<Neighbour> = Neighbour::ALL
+                .iter()
+                .copied()
+                .filter(|after| is_match(&resolved[*after as usize]))
+                .collect();
+            if accepted.len() == Neighbour::ALL.len() {
+                accepting_states.insert(id);
+            } else if !accepted.is_empty() {
+                look_accepting_states.insert(id, accepted);
+            }
+
+            let mut transition: BTreeMap<CharRange, usize> = BTreeMap::new();
+            for range in alphabet.iter() {
+                let after = Neighbour::of(Some(range.start));
+                let next_states = step(&resolved[after as usize], range.start);
+
+                if next_states.is_empty() {
+                    continue;
+                }
+                let next_id = DFA::get_id(&mut ids, &mut keys, (next_states, remembered(after)));
+                transition.insert(*range, next_id);
+            }
+            transitions.push(transition);
+        }
+
+        Some(DFA {
+            start_state: start_states[Neighbour::Edge as usize],
+            start_states,
+            transitions,
+            accepting_states,
+            look_accepting_states,
+        })
+    }
+
+    // ? the id of a DFA state, numbering it next if it is new
+    fn get_id(
+        ids: &mut HashMap<PowersetKey, usize>,
+        keys: &mut Vec<PowersetKey>,
+        key: PowersetKey,
+    ) -> usize {
+        *ids.entry(key).or_insert_with_key(|key| {
+            keys.push(key.clone());
+            keys.len() - 1
+        })
+    }
+
+    // ? The NFA's ranges cut into pieces that never partly overlap one of them, so
+    // ? every char of a piece moves the same way and the piece's first char can
+    // ? stand in for all of it. Anchored::No covers every char for the prefix loop.
+    // ? The chars an assertion tells apart are cut out too, so the first char also
+    // ? stands in for what the assertions see of the piece.
+    fn get_nfa_alphabet(nfa: &NFA, anchored: Anchored) -> Vec<CharRange> {
+        let looks = nfa
+            .states
+            .iter()
+            .flat_map(|state| state.look_transitions.iter())
+            .flat_map(|(look, _)| look.get_char_classes().iter().copied());
+        let mut ranges: Vec<CharRange> = nfa
+            .states
+            .iter()
+            .flat_map(|state| state.get_transition_ranges())
+            .chain(looks)
+            .collect::<BTreeSet<CharRange>>()
+            .into_iter()
+            .collect();
+        if anchored == Anchored::No {
+            ranges.push(CharRange::FULL);
+        }
+        CharRange::disjoint(&ranges)
+    }
+
+    // ? every NFA state reachable through ε and the assertions that hold, sorted so
+    // ? it can key a DFA state
+    fn closure(nfa: &NFA, states: &[StateId], holds: &dyn Fn(Look) -> bool) -> Vec<StateId> {
+        let mut closure: BTreeSet<StateId> = states.iter().copied().collect();
+        let mut stack: Vec<StateId> = states.to_vec();
+
+        while let Some(curr_state) = stack.pop() {
+            for next_state in DFA::get_epsilon_targets(nfa, curr_state, holds) {
+                if closure.insert(next_state) {
+                    stack.push(next_state);
+                }
+            }
+        }
+
+        closure.into_iter().collect()
+    }
+
+    // ? the ε moves, then the moves of every assertion that holds
+    fn get_epsilon_targets<'n>(
+        nfa: &'n NFA,
+        state: StateId,
+        holds: &'n dyn Fn(Look) -> bool,
+    ) -> impl DoubleEndedIterator<Item = StateId> + 'n {
+        let state = nfa.state(state);
+        let looks = state
+            .look_transitions
+            .iter()
+            .filter(|(look, _)| holds(*look))
+            .flat_map(|(_, next_states)| next_states.iter());
+        state.epsilon_transitions.iter().chain(looks).copied()
+    }
+
+    // ? Leftmost-first (Perl) semantics: a DFA state is the *ordered* list of NFA
+    // ? states in thread priority order, cut off after the first accepting one. The
+    // ? cut drops the same lower priority threads the Pike VM drops, so running the
+    // ? DFA until it dies ends where the leftmost-first match ends.
+    // ? Anchored::No adds the `.*?` prefix as a last, lowest priority thread
+    // ? (PREFIX_LOOP). It moves on every char and is cut like any other thread once
+    // ? a match is found, so the DFA dies after the leftmost match instead of
+    // ? looking for more.
+    pub fn from_nfa_leftmost_first(nfa: &NFA, anchored: Anchored) -> DFA {
+        DFA::from_nfa_leftmost_first_with_limit(nfa, anchored, usize::MAX).expect("no size limit")
+    }
+
+    // ? None once the DFA's table would have more than `size_limit` cells
+    pub fn from_nfa_leftmost_first_with_limit(
+        nfa: &NFA,
+        anchored: Anchored,
+        size_limit: usize,
+    ) -> Option<DFA> {
+        let alphabet = DFA::get_nfa_alphabet(nfa, anchored);
+
+        let start = match anchored {
+            Anchored::Yes => DFA::priority_closure(nfa, &[nfa.in_state], &|_| false),
+            Anchored::No => DFA::priority_closure(nfa, &[PREFIX_LOOP], &|_| false),
+        };
+
+        // ? walking the list again with the assertions keeps the priority order, a
+        // ? thread an assertion lets through lands right behind the one it came from
+        DFA::powerset(
+            nfa,
+            &alphabet,
+            start,
+            |states, holds| DFA::priority_closure(nfa, states, holds),
+            |states| {
+                states
+                    .last()
+                    .is_some_and(|state| *state != PREFIX_LOOP && nfa.state(*state).accepting)
+            },
+            |states, symbol| {
+                let moved: Vec<StateId> = states
+                    .iter()
+                    .flat_map(|state| match *state {
+                        PREFIX_LOOP => vec![PREFIX_LOOP],
+                        state => nfa.state(state).get_transitions_for_char(symbol).to_vec(),
+                    })
+                    .collect();
+                DFA::priority_closure(nfa, &moved, &|_| false)
+            },
+            size_limit,
+        )
+    }
+
+    // ? ε-closure in the order the Pike VM adds threads, stopping at the first
+    // ? accepting state since every thread after it has lower priority. The prefix
+    // ? loop is always last, it seeds a new thread at the pattern start and stays.
+    fn priority_closure(
+        nfa: &NFA,
+        states: &[StateId],
+        holds: &dyn Fn(Look) -> bool,
+    ) -> Vec<StateId> {
+        let (states, has_prefix_loop) = match states.split_last() {
+            Some((&PREFIX_LOOP, states)) => (states, true),
+            _ => (states, false),
+        };
+
+        let mut closure: Vec<StateId> = Vec::new();
+        let mut is_visited: HashSet<StateId> = HashSet::new();
+
+        if DFA::extend_priority_closure(nfa, &mut closure, &mut is_visited, states, holds) {
+            return closure;
+        }
+        if has_prefix_loop {
+            let in_state = [nfa.in_state];
+            if DFA::extend_priority_closure(nfa, &mut closure, &mut is_visited, &in_state, holds) {
+                return closure;
+            }
+            closure.push(PREFIX_LOOP);
+        }
+        closure
+    }
+
+    // ? returns true if it stopped at an accepting state
+    fn extend_priority_closure(
+        nfa: &NFA,
+        closure: &mut Vec<StateId>,
+        is_visited: &mut HashSet<StateId>,
+        states: &[StateId],
+        holds: &dyn Fn(Look) -> bool,
+    ) -> bool {
+        let mut stack: Vec<StateId> = states.iter().rev().copied().collect();
+
+        while let Some(curr_state) = stack.pop() {
+            if !is_visited.insert(curr_state) {
+                continue;
+            }
+            closure.push(curr_state);
+
+            if nfa.state(curr_state).accepting {
+                return true;
+            }
+            stack.extend(DFA::get_epsilon_targets(nfa, curr_state, holds).rev());
+        }
+
+        false
+    }
+
+    pub fn from_transition_table(
+        dfa_table: &DfaTable,
+        dfa_start: &[StateId],
+        accepting_states: &[Vec<StateId>],
+    ) -> DFA {
+        let mut all_states: Vec<&Vec<StateId>> = dfa_table.keys().collect();
+        all_states.sort();
+
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        ids.insert(DFA::get_set_label(dfa_start), 0);
+        for state in all_states.iter() {
+            let next_id = ids.len();
+            ids.entry(DFA::get_set_label(state)).or_insert(next_id);
+        }
+
+        let mut transitions: Vec<BTreeMap<CharRange, usize>> = vec![BTreeMap::new(); ids.len()];
+        for state in all_states {
+            let id = ids[&DFA::get_set_label(state)];
+            for cell in dfa_table[state].iter() {
+                let range = match cell.symbol {
+                    Symbol::Range(range) => range,
+                    Symbol::Look(_) | Symbol::Epsilon => continue,
+                };
+                if let Some(next_id) = ids.get(&cell.transition.join(",")) {
+                    transitions[id].insert(range, *next_id);
+                }
+            }
+        }
+
         DFA {
-            in_state: Rc::new(RefCell::new(State::new(false))),
-            out_state: Rc::new(RefCell::new(State::new(true))),
+            start_state: 0,
+            start_states: vec![0; Neighbour::ALL.len()],
+            transitions,
+            accepting_states: accepting_states
+                .iter()
+                .map(|state| ids[&DFA::get_set_label(state)])
+                .collect(),
+            look_accepting_states: HashMap::new(),
         }
     }
 
+    // ? same encoding as a CELL's transition list joined, so both can be looked up
+    fn get_set_label(states: &[StateId]) -> String {
+        states
+            .iter()
+            .map(|state| state.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+
     pub fn get_epsilon_and_unique_transitions(
-        transition_map: &HashMap<Uuid, Vec<CELL>>,
-    ) -> (Vec<Vec<String>>, HashSet<String>) {
+        transition_map: &NfaTable,
+    ) -> (Vec<Vec<String>>, HashSet<Symbol>) {
         let mut epsilon_transitions: Vec<Vec<String>> = Vec::new();
-        let mut unique_transitions: HashSet<String> = HashSet::new();
+        let mut unique_transitions: HashSet<Symbol> = HashSet::new();
 
-        for (_, cells) in transition_map {
+        for cells in transition_map.values() {
             let mut epsilon_transition: Vec<String> = Vec::new();
 
             for cell in cells {
-                if cell.symbol == EPSILON {
+                if cell.symbol == Symbol::Epsilon {
                     epsilon_transition.extend(cell.transition.iter().cloned());
                 } else {
-                    unique_transitions.insert(cell.symbol.clone());
+                    unique_transitions.insert(cell.symbol);
                 }
             }
 
@@ -48,46 +415,244 @@ impl DFA {
         (epsilon_transitions, unique_transitions)
     }
 
-    // ! WIP DFA
+    // ? NFA table cells store their targets as state id strings
+    fn get_label_map(nfa_table: &NfaTable) -> HashMap<String, StateId> {
+        nfa_table
+            .keys()
+            .map(|label| (label.to_string(), *label))
+            .collect()
+    }
+
+    // ? `symbol` is ε or a piece of the alphabet, which lies either entirely
+    // ? inside or entirely outside each range of the table
+    fn get_targets(
+        nfa_table: &NfaTable,
+        label_map: &HashMap<String, StateId>,
+        state: &StateId,
+        symbol: Symbol,
+    ) -> Vec<StateId> {
+        let is_covered = |cell: &&CELL| match (cell.symbol, symbol) {
+            (Symbol::Range(range), Symbol::Range(piece)) => range.contains(piece.start),
+            (cell_symbol, symbol) => cell_symbol == symbol,
+        };
+        match nfa_table.get(state) {
+            Some(cells) => cells
+                .iter()
+                .filter(is_covered)
+                .flat_map(|cell| cell.transition.iter())
+                .filter_map(|label| label_map.get(label).copied())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // ? every state reachable through ε only, returned sorted so it can key the DFA table
+    pub fn epsilon_closure(
+        nfa_table: &NfaTable,
+        label_map: &HashMap<String, StateId>,
+        states: &[StateId],
+    ) -> Vec<StateId> {
+        let mut closure: HashSet<StateId> = states.iter().copied().collect();
+        let mut stack: Vec<StateId> = states.to_vec();
+
+        while let Some(curr_state) = stack.pop() {
+            for next_state in DFA::get_targets(nfa_table, label_map, &curr_state, Symbol::Epsilon) {
+                if closure.insert(next_state) {
+                    stack.push(next_state);
+                }
+            }
+        }
+
+        let mut closure: Vec<StateId> = closure.into_iter().collect();
+        closure.sort();
+        closure
+    }
+
+    pub fn move_on_symbol(
+        nfa_table: &NfaTable,
+        label_map: &HashMap<String, StateId>,
+        states: &[StateId],
+        symbol: Symbol,
+    ) -> Vec<StateId> {
+        let mut next_states: Vec<StateId> = states
+            .iter()
+            .flat_map(|state| DFA::get_targets(nfa_table, label_map, state, symbol))
+            .collect();
+        next_states.sort();
+        next_states.dedup();
+        next_states
+    }
+
+    // ? Powerset construction: each DFA state is the ε-closure of a set of NFA states.
+    // ? Returns the table, the start state and every state holding the NFA accepting state.
+    // ? A symbol with no NFA move keeps an empty transition list (the dead state).
+    // ? Assertions are never taken here, `from_nfa` is the one that knows about them.
     pub fn get_transition_table(
-        nfa_table: HashMap<Uuid, Vec<CELL>>,
-        accepting_state: Uuid,
-        all_e_transitions: Vec<CELL>,
-    ) -> (HashMap<Vec<Uuid>, Vec<CELL>>, Vec<Uuid>) {
-        let mut dfa_table: HashMap<Vec<Uuid>, Vec<CELL>> = HashMap::new();
-        let mut accepting_states: Vec<Uuid> = Vec::new();
-
-        let (epsilon_transitions, unique_transitions) =
-            DFA::get_epsilon_and_unique_transitions(&nfa_table);
-
-        let uuid_epsilon_transitions = convert_to_uuid(&epsilon_transitions);
-
-        for transition in uuid_epsilon_transitions {
-            if transition.len() >= 2 {
-                let mut cells: Vec<CELL> = Vec::new();
-                for symbol in unique_transitions.iter() {
-                    let new_cell = CELL::new(&symbol);
-                    cells.push(new_cell);
+        nfa_table: NfaTable,
+        start_state: StateId,
+        accepting_state: StateId,
+    ) -> (DfaTable, Vec<StateId>, Vec<Vec<StateId>>) {
+        let mut dfa_table: DfaTable = BTreeMap::new();
+        let mut accepting_states: Vec<Vec<StateId>> = Vec::new();
+
+        let label_map = DFA::get_label_map(&nfa_table);
+        let (_, unique_transitions) = DFA::get_epsilon_and_unique_transitions(&nfa_table);
+        let ranges: Vec<CharRange> = unique_transitions
+            .into_iter()
+            .filter_map(|symbol| match symbol {
+                Symbol::Range(range) => Some(range),
+                Symbol::Look(_) | Symbol::Epsilon => None,
+            })
+            .collect();
+        let alphabet = CharRange::disjoint(&ranges);
+
+        let dfa_start = DFA::epsilon_closure(&nfa_table, &label_map, &[start_state]);
+        let mut stack: Vec<Vec<StateId>> = vec![dfa_start.clone()];
+
+        while let Some(curr_states) = stack.pop() {
+            if dfa_table.contains_key(&curr_states) {
+                continue;
+            }
+
+            let mut cells: Vec<CELL> = Vec::new();
+            for range in alphabet.iter() {
+                let symbol = Symbol::Range(*range);
+                let mut new_cell = CELL::new(symbol);
+                let moved = DFA::move_on_symbol(&nfa_table, &label_map, &curr_states, symbol);
+
+                if !moved.is_empty() {
+                    let next_states = DFA::epsilon_closure(&nfa_table, &label_map, &moved);
+                    for state in next_states.iter() {
+                        new_cell.add_transition(&state.to_string());
+                    }
+                    if !dfa_table.contains_key(&next_states) {
+                        stack.push(next_states);
+                    }
                 }
-                dfa_table.insert(transition, cells);
+                cells.push(new_cell);
+            }
+
+            if curr_states.contains(&accepting_state) {
+                accepting_states.push(curr_states.clone());
+            }
+            dfa_table.insert(curr_states, cells);
+        }
+
+        (dfa_table, dfa_start, accepting_states)
+    }
+
+    // ? None is the dead state, no input can lead back to an accepting state. The
+    // ? only range that can hold the char is the last one starting at or before it.
+    pub fn next_state(&self, state: usize, symbol: char) -> Option<usize> {
+        let (range, next_state) = self.transitions[state]
+            .range(..=CharRange::new(symbol, char::MAX))
+            .next_back()?;
+        range.contains(symbol).then_some(*next_state)
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting_states.contains(&state)
+    }
+
+    // ? whether the state accepts when the next char (or the end) looks like `after`
+    pub fn is_accepting_before(&self, state: usize, after: Neighbour) -> bool {
+        self.is_accepting(state)
+            || self
+                .look_accepting_states
+                .get(&state)
+                .is_some_and(|accepted| accepted.contains(&after))
+    }
+
+    pub fn get_start_state(&self, before: Neighbour) -> usize {
+        self.start_states[before as usize]
+    }
+
+    // ? Runs the DFA from `start` until it dies and returns the last position it
+    // ? accepted at, i.e. the end of the longest match starting at `start`.
+    pub fn longest_match_at(&self, haystack: &str, start: usize) -> Option<usize> {
+        let mut curr_state = self.get_start_state(Neighbour::before(haystack, start));
+        let mut match_end = self
+            .is_accepting_before(curr_state, Neighbour::after(haystack, start))
+            .then_some(start);
+
+        for (pos, symbol) in haystack[start..].char_indices() {
+            curr_state = match self.next_state(curr_state, symbol) {
+                Some(next_state) => next_state,
+                None => break,
+            };
+            let end = start + pos + symbol.len_utf8();
+            if self.is_accepting_before(curr_state, Neighbour::after(haystack, end)) {
+                match_end = Some(end);
+            }
+        }
+
+        match_end
+    }
+
+    // ? The first position the DFA accepts at, on an unanchored DFA the end of
+    // ? whichever match starting at or after `start` ends first. That is as soon as
+    // ? `is_match` can say yes.
+    pub fn earliest_match_end(&self, haystack: &str, start: usize) -> Option<usize> {
+        let mut curr_state = self.get_start_state(Neighbour::before(haystack, start));
+        if self.is_accepting_before(curr_state, Neighbour::after(haystack, start)) {
+            return Some(start);
+        }
+
+        for (pos, symbol) in haystack[start..].char_indices() {
+            curr_state = self.next_state(curr_state, symbol)?;
+            let end = start + pos + symbol.len_utf8();
+            if self.is_accepting_before(curr_state, Neighbour::after(haystack, end)) {
+                return Some(end);
             }
         }
 
-        (dfa_table, accepting_states)
+        None
     }
 
-    pub fn test(_string: &str) -> bool {
-        return false;
+    // ? For a DFA of a reversed NFA: reads haystack[start..end] backwards from `end`
+    // ? and returns the smallest position it accepted at, the leftmost start of a
+    // ? match ending at `end`. Backwards the char after a position comes before it,
+    // ? and the chars outside haystack[start..end] are still seen by assertions.
+    pub fn longest_match_rev(&self, haystack: &str, start: usize, end: usize) -> Option<usize> {
+        let mut curr_state = self.get_start_state(Neighbour::after(haystack, end));
+        let mut match_start = self
+            .is_accepting_before(curr_state, Neighbour::before(haystack, end))
+            .then_some(end);
+
+        for (pos, symbol) in haystack[start..end].char_indices().rev() {
+            curr_state = match self.next_state(curr_state, symbol) {
+                Some(next_state) => next_state,
+                None => break,
+            };
+            if self.is_accepting_before(curr_state, Neighbour::before(haystack, start + pos)) {
+                match_start = Some(start + pos);
+            }
+        }
+
+        match_start
+    }
+
+    pub fn test(&self, _string: &str) -> bool {
+        let mut curr_state = self.start_state;
+
+        for symbol in _string.chars() {
+            match self.next_state(curr_state, symbol) {
+                Some(next_state) => curr_state = next_state,
+                None => return false,
+            }
+        }
+
+        self.is_accepting_before(curr_state, Neighbour::Edge)
     }
 
-    pub fn print_dfa_transition_table(dfa_table: &HashMap<Vec<Uuid>, Vec<CELL>>) {
+    pub fn print_dfa_transition_table(dfa_table: &DfaTable) {
         println!("{:<36} {:<10} {:<10}", "State", "Symbol", "Transitions");
         println!("{:-<60}", "-");
 
         for (state, cells) in dfa_table {
             let state_str = state
                 .iter()
-                .map(|uuid| uuid.to_string())
+                .map(|state| state.to_string())
                 .collect::<Vec<_>>()
                 .join(", ");
 
@@ -95,7 +660,7 @@ impl DFA {
                 let transitions_str = cell
                     .transition
                     .iter()
-                    .map(|uuid| uuid.to_string())
+                    .map(|state| state.to_string())
                     .collect::<Vec<_>>()
                     .join(", ");
 
@@ -106,35 +671,285 @@ impl DFA {
             }
         }
     }
-    pub fn get_accepting_states() {}
+    pub fn get_accepting_states(&self) -> Vec<usize> {
+        let mut accepting_states: Vec<usize> = self.accepting_states.iter().copied().collect();
+        accepting_states.sort();
+        accepting_states
+    }
+
+    pub fn get_alphabet(&self) -> Vec<CharRange> {
+        self.transitions
+            .iter()
+            .flat_map(|transition| transition.keys().copied())
+            .collect::<BTreeSet<CharRange>>()
+            .into_iter()
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{dfa::DFA, nfa::NFA};
+    use crate::{
+        compiler::Compiler, dfa::DFA, look::Neighbour, nfa::NFA, parser::Parser, search::Anchored,
+        state::CharRange,
+    };
+
+    fn get_dfa_table(nfa: &NFA) -> (usize, usize) {
+        let nfa_table = nfa.get_transition_table();
+        let start_state = nfa.in_state;
+        let dfa_table = DFA::get_transition_table(nfa_table.0, start_state, nfa_table.1);
+        DFA::print_dfa_transition_table(&dfa_table.0);
+        (dfa_table.0.len(), dfa_table.2.len())
+    }
 
     #[test]
     fn test_get_dfa_transition_table() {
-        let mut first_nfa = NFA::char("a");
-        let mut second_nfa = NFA::char("b");
+        let first_nfa = NFA::char('a');
+        let second_nfa = NFA::char('b');
 
-        let final_nfa = NFA::or_pair(&mut first_nfa, &mut second_nfa);
+        let final_nfa = NFA::or_pair(first_nfa, second_nfa);
 
         let nfa_table = final_nfa.get_transition_table();
-        let dfa_table = DFA::get_transition_table(nfa_table.0, nfa_table.1, nfa_table.2);
-        DFA::print_dfa_transition_table(&dfa_table.0);
-        // println!("Accepting States: {:?}", accepting_states);
+        let start_state = final_nfa.in_state;
+        let (dfa_table, dfa_start, accepting_states) =
+            DFA::get_transition_table(nfa_table.0, start_state, nfa_table.1);
+
+        // ? start, after 'a', after 'b'
+        assert_eq!(dfa_table.len(), 3);
+        assert_eq!(accepting_states.len(), 2);
+        assert!(dfa_start.contains(&start_state));
+        assert!(!accepting_states.contains(&dfa_start));
+
+        let accepting_labels: Vec<Vec<String>> = accepting_states
+            .iter()
+            .map(|state| state.iter().map(|id| id.to_string()).collect())
+            .collect();
+        for cell in dfa_table.get(&dfa_start).unwrap() {
+            assert!(accepting_labels.contains(&cell.transition));
+        }
     }
-}
 
-fn convert_to_uuid(input: &Vec<Vec<String>>) -> Vec<Vec<Uuid>> {
-    input
-        .into_iter()
-        .map(|inner_vec| {
-            inner_vec
-                .into_iter()
-                .map(|s| Uuid::parse_str(&s).expect("Failed to parse UUID"))
-                .collect()
-        })
-        .collect()
+    #[test]
+    fn test_get_dfa_transition_table_follows_closure() {
+        let nfa = Compiler::compile(&Parser::parse("(a|b)*abb").unwrap());
+
+        // ? the textbook subset construction for (a|b)*abb gives five states, one accepting
+        assert_eq!(get_dfa_table(&nfa), (5, 1));
+
+        let nfa = Compiler::compile(&Parser::parse("a*").unwrap());
+        assert_eq!(get_dfa_table(&nfa), (2, 2));
+    }
+
+    #[test]
+    fn test_from_nfa() {
+        let nfa = Compiler::compile(&Parser::parse("(a|b)*abb").unwrap());
+        let dfa = DFA::from_nfa(&nfa);
+
+        assert_eq!(dfa.state_count(), 5);
+        assert_eq!(dfa.accepting_states.len(), 1);
+        assert_eq!(dfa.start_state, 0);
+        assert!(!dfa.accepting_states.contains(&dfa.start_state));
+    }
+
+    #[test]
+    fn test_from_nfa_with_limit() {
+        // ? the DFA remembers which of the last four chars were 'a'
+        let nfa = Compiler::compile(&Parser::parse("(a|b)*a(a|b){3}").unwrap());
+        let state_count = DFA::from_nfa(&nfa).state_count();
+
+        assert_eq!(DFA::from_nfa(&nfa).minimize_hopcroft().state_count(), 16);
+        // ? each state has a cell for 'a' and one for 'b'
+        assert!(DFA::from_nfa_with_limit(&nfa, 2 * state_count - 1).is_none());
+        assert_eq!(
+            DFA::from_nfa_with_limit(&nfa, 2 * state_count),
+            Some(DFA::from_nfa(&nfa))
+        );
+        assert!(DFA::from_nfa_leftmost_first_with_limit(&nfa, Anchored::No, 32).is_none());
+    }
+
+    #[test]
+    fn test_dfa_test() {
+        let dfa = DFA::from_nfa(&Compiler::compile(&Parser::parse("(a|b)*abb").unwrap()));
+
+        assert!(dfa.test("abb"));
+        assert!(dfa.test("aabb"));
+        assert!(dfa.test("babaabb"));
+        assert!(!dfa.test(""));
+        assert!(!dfa.test("ab"));
+        assert!(!dfa.test("abba"));
+        assert!(!dfa.test("abbc"));
+    }
+
+    #[test]
+    fn test_dfa_test_agrees_with_nfa() {
+        let inputs = [
+            "", "a", "b", "ab", "ba", "abc", "aab", "abab", "cab", "abcabc",
+        ];
+
+        for pattern in ["a*", "ab|ba", "(ab)*c*", "a(b|c)*", "[a-c]*b", "(a*)*b"] {
+            let nfa = Compiler::compile(&Parser::parse(pattern).unwrap());
+            let dfa = DFA::from_nfa(&nfa);
+
+            for input in inputs {
+                assert_eq!(
+                    dfa.test(input),
+                    nfa.test(input),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_nfa_class_ranges() {
+        let nfa = Compiler::compile(&Parser::parse("[a-z]*[^a-z]").unwrap());
+        let dfa = DFA::from_nfa(&nfa);
+
+        // ? a-z and everything else, however many chars the classes hold
+        assert_eq!(
+            dfa.get_alphabet(),
+            vec![
+                CharRange::new('\0', '`'),
+                CharRange::new('a', 'z'),
+                CharRange::new('{', char::MAX)
+            ]
+        );
+        assert!(dfa.test("hello!"));
+        assert!(dfa.test("é"));
+        assert!(!dfa.test("hello"));
+        assert!(!dfa.test("a!!"));
+        assert_eq!(dfa.next_state(dfa.start_state, 'q'), dfa.next_state(0, 'a'));
+    }
+
+    #[test]
+    fn test_longest_match_at() {
+        let dfa = DFA::from_nfa(&Compiler::compile(&Parser::parse("ab*").unwrap()));
+
+        assert_eq!(dfa.longest_match_at("xabbbc", 1), Some(5));
+        assert_eq!(dfa.longest_match_at("xabbbc", 0), None);
+        assert_eq!(dfa.longest_match_at("a", 0), Some(1));
+    }
+
+    #[test]
+    fn test_earliest_match_end() {
+        let nfa = Compiler::compile(&Parser::parse("aab|b*c").unwrap());
+        let dfa = DFA::from_nfa_leftmost_first(&nfa, Anchored::No);
+
+        // ? a failed "aa" prefix must not hide the "aab" that overlaps it
+        assert_eq!(dfa.earliest_match_end("aaab", 0), Some(4));
+        // ? chars outside the alphabet restart the search
+        assert_eq!(dfa.earliest_match_end("xyzbbc", 0), Some(6));
+        assert_eq!(dfa.earliest_match_end("aabc", 3), Some(4));
+        assert_eq!(dfa.earliest_match_end("aab", 1), None);
+        assert_eq!(dfa.earliest_match_end("xyz", 0), None);
+    }
+
+    #[test]
+    fn test_unanchored_stops_after_leftmost_match() {
+        let nfa = Compiler::compile(&Parser::parse("ab|cd*").unwrap());
+        let dfa = DFA::from_nfa_leftmost_first(&nfa, Anchored::No).minimize_hopcroft();
+
+        // ? "cddd" is a match too but starts after "ab", the DFA dies at 3
+        assert_eq!(dfa.longest_match_at("xabcddd", 0), Some(3));
+        assert_eq!(dfa.longest_match_at("x-cdd-", 0), Some(5));
+        assert_eq!(dfa.longest_match_at("xyz", 0), None);
+    }
+
+    #[test]
+    fn test_from_nfa_assertions() {
+        let nfa = Compiler::compile(&Parser::parse("^a|b$").unwrap());
+        let dfa = DFA::from_nfa_leftmost_first(&nfa, Anchored::No);
+
+        assert_eq!(dfa.longest_match_at("ab", 0), Some(1));
+        assert_eq!(dfa.longest_match_at("ab", 1), Some(2));
+        assert_eq!(dfa.longest_match_at("aba", 1), None);
+        assert_eq!(dfa.earliest_match_end("xxb", 0), Some(3));
+        assert_eq!(dfa.earliest_match_end("bxa", 0), None);
+
+        // ? `$` doesn't change where `b` leads, only when it accepts
+        let dfa = DFA::from_nfa(&Compiler::compile(&Parser::parse("ab*$").unwrap()));
+        let dfa = dfa.minimize_hopcroft();
+        assert_eq!(dfa.state_count(), 2);
+        assert!(dfa.accepting_states.is_empty());
+        assert_eq!(dfa.look_accepting_states[&1], vec![Neighbour::Edge]);
+        assert!(dfa.test("abb"));
+        assert_eq!(dfa.longest_match_at("abbx", 0), None);
+
+        // ? `^` needs to know whether anything came before
+        assert_eq!(dfa.start_states, vec![0; Neighbour::ALL.len()]);
+        let dfa = DFA::from_nfa(&Compiler::compile(&Parser::parse("a*^b").unwrap()));
+        assert_ne!(dfa.get_start_state(Neighbour::Other), dfa.start_state);
+        assert!(dfa.test("b"));
+        assert!(!dfa.test("ab"));
+        assert_eq!(dfa.longest_match_at("bb", 1), None);
+    }
+
+    #[test]
+    fn test_from_nfa_word_boundary() {
+        let nfa = Compiler::compile(&Parser::parse("\\bab*\\b").unwrap());
+        let dfa = DFA::from_nfa_leftmost_first(&nfa, Anchored::Yes).minimize_hopcroft();
+
+        // ? the start state remembers whether a word char came before
+        assert_eq!(dfa.get_start_state(Neighbour::Edge), dfa.start_state);
+        assert_eq!(
+            dfa.get_start_state(Neighbour::Other),
+            dfa.get_start_state(Neighbour::Edge)
+        );
+        assert_ne!(
+            dfa.get_start_state(Neighbour::Word),
+            dfa.get_start_state(Neighbour::Edge)
+        );
+        assert_eq!(dfa.longest_match_at("abb c", 0), Some(3));
+        assert_eq!(dfa.longest_match_at("x ab", 2), Some(4));
+        assert_eq!(dfa.longest_match_at("xab", 1), None);
+        assert_eq!(dfa.longest_match_at("abbc", 0), None);
+        assert_eq!(dfa.longest_match_at("abé", 0), None);
+
+        let nfa = Compiler::compile(&Parser::parse_with_unicode("a\\b", false).unwrap());
+        let dfa = DFA::from_nfa_leftmost_first(&nfa, Anchored::Yes);
+        assert_eq!(dfa.longest_match_at("abé", 0), None);
+        assert_eq!(dfa.longest_match_at("aé", 0), Some(1));
+    }
+
+    #[test]
+    fn test_longest_match_rev_assertions() {
+        let nfa = Compiler::compile(&Parser::parse("^a*b").unwrap());
+        let dfa = DFA::from_nfa(&nfa.reverse());
+
+        assert_eq!(dfa.longest_match_rev("aab", 0, 3), Some(0));
+        assert_eq!(dfa.longest_match_rev("xaab", 1, 4), None);
+        // ? the reverse scan stops at `start` but sees the char before it
+        assert_eq!(dfa.longest_match_rev("aab", 1, 3), None);
+    }
+
+    #[test]
+    fn test_longest_match_rev() {
+        let nfa = Compiler::compile(&Parser::parse("a*b").unwrap());
+        let dfa = DFA::from_nfa(&nfa.reverse());
+
+        assert_eq!(dfa.longest_match_rev("xaaab", 0, 5), Some(1));
+        assert_eq!(dfa.longest_match_rev("xaaab", 3, 5), Some(3));
+        assert_eq!(dfa.longest_match_rev("xaaab", 0, 4), None);
+    }
+
+    #[test]
+    fn test_from_nfa_leftmost_first() {
+        let nfa = Compiler::compile(&Parser::parse("a|ab").unwrap());
+        let longest = DFA::from_nfa(&nfa);
+        let first = DFA::from_nfa_leftmost_first(&nfa, Anchored::Yes);
+
+        assert_eq!(longest.longest_match_at("ab", 0), Some(2));
+        assert_eq!(first.longest_match_at("ab", 0), Some(1));
+
+        // ? the greedy star still runs as far as it can
+        let nfa = Compiler::compile(&Parser::parse("(ab)*|a").unwrap());
+        let first = DFA::from_nfa_leftmost_first(&nfa, Anchored::Yes);
+        assert_eq!(first.longest_match_at("ababa", 0), Some(4));
+        assert_eq!(
+            first.minimize_hopcroft().longest_match_at("ababa", 0),
+            Some(4)
+        );
+    }
 }
@@ -0,0 +1,147 @@
+use std::fmt;
+
+// ? byte offsets into the pattern, end is exclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegexError {
+    UnclosedGroup(Span),
+    UnmatchedGroupClose(Span),
+    NothingToRepeat(Span),
+    EmptyExpression(Span),
+    DanglingEscape(Span),
+    BadEscape(Span),
+    UnclosedClass(Span),
+    EmptyClass(Span),
+    InvalidClassRange(Span),
+    UnknownGroupKind(Span),
+    InvalidGroupName(Span),
+    DuplicateGroupName(Span),
+    InvalidRepetition(Span),
+    InvalidRepetitionRange(Span),
+    SizeLimitExceeded(Span),
+}
+
+impl RegexError {
+    pub fn span(&self) -> Span {
+        match self {
+            RegexError::UnclosedGroup(span)
+            | RegexError::UnmatchedGroupClose(span)
+            | RegexError::NothingToRepeat(span)
+            | RegexError::EmptyExpression(span)
+            | RegexError::DanglingEscape(span)
+            | RegexError::BadEscape(span)
+            | RegexError::UnclosedClass(span)
+            | RegexError::EmptyClass(span)
+            | RegexError::InvalidClassRange(span)
+            | RegexError::UnknownGroupKind(span)
+            | RegexError::InvalidGroupName(span)
+            | RegexError::DuplicateGroupName(span)
+            | RegexError::InvalidRepetition(span)
+            | RegexError::InvalidRepetitionRange(span)
+            | RegexError::SizeLimitExceeded(span) => *span,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RegexError::UnclosedGroup(_) => "unclosed group, missing ')'",
+            RegexError::UnmatchedGroupClose(_) => "unmatched ')'",
+            RegexError::NothingToRepeat(_) => "repetition operator has nothing to repeat",
+            RegexError::EmptyExpression(_) => "empty alternation branch or group",
+            RegexError::DanglingEscape(_) => "pattern ends with a dangling '\\'",
+            RegexError::BadEscape(_) => "unrecognized escape sequence",
+            RegexError::UnclosedClass(_) => "unclosed character class, missing ']'",
+            RegexError::EmptyClass(_) => "empty character class",
+            RegexError::InvalidClassRange(_) => "character class range is out of order",
+            RegexError::UnknownGroupKind(_) => "unknown group kind after '(?'",
+            RegexError::InvalidGroupName(_) => "invalid capture group name",
+            RegexError::DuplicateGroupName(_) => "duplicate capture group name",
+            RegexError::InvalidRepetition(_) => {
+                "invalid counted repetition, expected {m}, {m,} or {m,n}"
+            }
+            RegexError::InvalidRepetitionRange(_) => "counted repetition range is out of order",
+            RegexError::SizeLimitExceeded(_) => "compiled pattern exceeds the size limit",
+        }
+    }
+
+    // ? prints the pattern with the offending span underlined, e.g.
+    // ?   error: unmatched ')'
+    // ?     ab)c
+    // ?       ^
+    pub fn render(&self, pattern: &str) -> String {
+        let span = self.span();
+        let start = span.start.min(pattern.len());
+        let end = span.end.clamp(start, pattern.len());
+
+        let column = pattern[..start].chars().count();
+        let width = pattern[start..end].chars().count().max(1);
+
+        format!(
+            "error: {}\n  {}\n  {}^{}",
+            self.reason(),
+            pattern,
+            " ".repeat(column),
+            "~".repeat(width - 1)
+        )
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{} at {}..{}", self.reason(), span.start, span.end)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_render_single_char() {
+        let error = RegexError::UnmatchedGroupClose(Span::new(2, 3));
+
+        assert_eq!(error.render("ab)c"), "error: unmatched ')'\n  ab)c\n    ^");
+    }
+
+    #[test]
+    fn test_render_underline() {
+        let error = RegexError::UnclosedGroup(Span::new(1, 4));
+
+        assert_eq!(
+            error.render("a(bc"),
+            "error: unclosed group, missing ')'\n  a(bc\n   ^~~"
+        );
+    }
+
+    #[test]
+    fn test_render_counts_chars_not_bytes() {
+        let error = RegexError::NothingToRepeat(Span::new(4, 5));
+
+        assert_eq!(
+            error.render("éé*"),
+            "error: repetition operator has nothing to repeat\n  éé*\n    ^"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let error = RegexError::BadEscape(Span::new(0, 2));
+
+        assert_eq!(error.to_string(), "unrecognized escape sequence at 0..2");
+    }
+}
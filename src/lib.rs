@@ -0,0 +1,14 @@
+pub mod ast;
+pub mod captures;
+pub mod compiler;
+pub mod dfa;
+pub mod error;
+pub mod look;
+pub mod minimize;
+pub mod nfa;
+pub mod parser;
+pub mod pikevm;
+pub mod regex;
+pub mod replace;
+pub mod search;
+pub mod state;
@@ -0,0 +1,219 @@
+use crate::state::CharRange;
+use std::fmt;
+use std::sync::OnceLock;
+
+// ? Zero-width assertions. They consume nothing and hold or not depending on the
+// ? chars on either side of a position. There is no multi-line mode, so `^` and
+// ? `\A` are both Start and `$` and `\z` are both End. A word boundary is
+// ? between a word char and anything else, the edges included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Look {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    AsciiWordBoundary,
+    AsciiNotWordBoundary,
+}
+
+// ? all an assertion gets to know about the char on one side of a position,
+// ? Edge is the start or the end of the haystack. AsciiWord is `[0-9A-Za-z_]`,
+// ? Word any other alphanumeric char and Other everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Neighbour {
+    Edge,
+    Other,
+    Word,
+    AsciiWord,
+}
+
+const ASCII_WORD_RANGES: [CharRange; 4] = [
+    CharRange {
+        start: '0',
+        end: '9',
+    },
+    CharRange {
+        start: 'A',
+        end: 'Z',
+    },
+    CharRange {
+        start: '_',
+        end: '_',
+    },
+    CharRange {
+        start: 'a',
+        end: 'z',
+    },
+];
+
+impl Neighbour {
+    pub const ALL: [Neighbour; 4] = [
+        Neighbour::Edge,
+        Neighbour::Other,
+        Neighbour::Word,
+        Neighbour::AsciiWord,
+    ];
+
+    pub fn of(symbol: Option<char>) -> Neighbour {
+        match symbol {
+            Some(symbol) if symbol.is_ascii_alphanumeric() || symbol == '_' => Neighbour::AsciiWord,
+            Some(symbol) if symbol.is_alphanumeric() => Neighbour::Word,
+            Some(_) => Neighbour::Other,
+            None => Neighbour::Edge,
+        }
+    }
+
+    pub fn is_word(self) -> bool {
+        matches!(self, Neighbour::Word | Neighbour::AsciiWord)
+    }
+
+    pub fn is_ascii_word(self) -> bool {
+        self == Neighbour::AsciiWord
+    }
+
+    pub fn before(haystack: &str, pos: usize) -> Neighbour {
+        Neighbour::of(haystack[..pos].chars().next_back())
+    }
+
+    pub fn after(haystack: &str, pos: usize) -> Neighbour {
+        Neighbour::of(haystack[pos..].chars().next())
+    }
+}
+
+impl Look {
+    pub fn is_match(self, before: Neighbour, after: Neighbour) -> bool {
+        match self {
+            Look::Start => before == Neighbour::Edge,
+            Look::End => after == Neighbour::Edge,
+            Look::WordBoundary => before.is_word() != after.is_word(),
+            Look::NotWordBoundary => before.is_word() == after.is_word(),
+            Look::AsciiWordBoundary => before.is_ascii_word() != after.is_ascii_word(),
+            Look::AsciiNotWordBoundary => before.is_ascii_word() == after.is_ascii_word(),
+        }
+    }
+
+    pub fn is_match_at(self, haystack: &str, pos: usize) -> bool {
+        self.is_match(
+            Neighbour::before(haystack, pos),
+            Neighbour::after(haystack, pos),
+        )
+    }
+
+    // ? the same assertion seen by a reversed NFA, which reads the haystack backwards
+    pub fn reversed(self) -> Look {
+        match self {
+            Look::Start => Look::End,
+            Look::End => Look::Start,
+            look => look,
+        }
+    }
+
+    // ? whether the char before the position matters, a DFA has to remember it
+    pub fn looks_behind(self) -> bool {
+        self != Look::End
+    }
+
+    // ? The chars the assertion tells apart. A DFA cuts its alphabet at their
+    // ? boundaries, so every char of a piece looks the same to the assertion.
+    pub fn get_char_classes(self) -> &'static [CharRange] {
+        match self {
+            Look::Start | Look::End => &[],
+            Look::AsciiWordBoundary | Look::AsciiNotWordBoundary => &ASCII_WORD_RANGES,
+            Look::WordBoundary | Look::NotWordBoundary => Look::get_word_ranges(),
+        }
+    }
+
+    // ? every alphanumeric char and '_' as sorted ranges, worked out once by
+    // ? asking every char since std has no table of them
+    fn get_word_ranges() -> &'static [CharRange] {
+        static WORD_RANGES: OnceLock<Vec<CharRange>> = OnceLock::new();
+        WORD_RANGES.get_or_init(|| {
+            let word_chars: Vec<CharRange> = (char::MIN..=char::MAX)
+                .filter(|symbol| Neighbour::of(Some(*symbol)).is_word())
+                .map(CharRange::single)
+                .collect();
+            CharRange::canonicalize(&word_chars)
+        })
+    }
+}
+
+impl fmt::Display for Look {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Look::Start => write!(f, "\\A"),
+            Look::End => write!(f, "\\z"),
+            Look::WordBoundary => write!(f, "\\b"),
+            Look::NotWordBoundary => write!(f, "\\B"),
+            Look::AsciiWordBoundary => write!(f, "(?-u:\\b)"),
+            Look::AsciiNotWordBoundary => write!(f, "(?-u:\\B)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::look::{Look, Neighbour};
+
+    #[test]
+    fn test_is_match_at() {
+        assert!(Look::Start.is_match_at("ab", 0));
+        assert!(!Look::Start.is_match_at("ab", 1));
+        assert!(Look::End.is_match_at("ab", 2));
+        assert!(!Look::End.is_match_at("ab", 1));
+        assert!(Look::Start.is_match_at("", 0) && Look::End.is_match_at("", 0));
+        assert_eq!(Neighbour::before("é", 2), Neighbour::Word);
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let haystack = "ab cé-";
+        let boundaries: Vec<usize> = (0..=haystack.len())
+            .filter(|pos| haystack.is_char_boundary(*pos))
+            .filter(|pos| Look::WordBoundary.is_match_at(haystack, *pos))
+            .collect();
+        assert_eq!(boundaries, vec![0, 2, 3, 6]);
+
+        // ? 'é' is no ASCII word char, so the word ends before it
+        let boundaries: Vec<usize> = (0..=haystack.len())
+            .filter(|pos| haystack.is_char_boundary(*pos))
+            .filter(|pos| Look::AsciiWordBoundary.is_match_at(haystack, *pos))
+            .collect();
+        assert_eq!(boundaries, vec![0, 2, 3, 4]);
+
+        assert!(Look::NotWordBoundary.is_match_at(haystack, 1));
+        assert!(Look::NotWordBoundary.is_match_at("", 0));
+        assert!(!Look::AsciiNotWordBoundary.is_match_at(haystack, 4));
+    }
+
+    #[test]
+    fn test_get_char_classes() {
+        let word_ranges = Look::WordBoundary.get_char_classes();
+        for symbol in ['a', 'Z', '7', '_', 'é', 'ß', 'ж', '中'] {
+            assert!(word_ranges.iter().any(|range| range.contains(symbol)));
+        }
+        for symbol in [' ', '-', '€', '\0', char::MAX] {
+            assert!(!word_ranges.iter().any(|range| range.contains(symbol)));
+        }
+        assert_eq!(Look::AsciiWordBoundary.get_char_classes().len(), 4);
+        assert!(Look::Start.get_char_classes().is_empty());
+    }
+
+    #[test]
+    fn test_reversed() {
+        for look in [
+            Look::Start,
+            Look::End,
+            Look::WordBoundary,
+            Look::AsciiNotWordBoundary,
+        ] {
+            for before in Neighbour::ALL {
+                for after in Neighbour::ALL {
+                    assert_eq!(
+                        look.reversed().is_match(after, before),
+                        look.is_match(before, after)
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1 @@
-mod dfa;
-mod nfa;
-mod state;
-
 fn main() {}
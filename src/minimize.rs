@@ -0,0 +1,441 @@
+use crate::dfa::DFA;
+use crate::look::Neighbour;
+use crate::state::CharRange;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// ? state -> symbol -> every state reachable on it, a DFA read backwards
+type NfaEdges = Vec<BTreeMap<CharRange, Vec<usize>>>;
+
+impl DFA {
+    // ? Hopcroft's partition refinement. The DFA is first completed with an explicit
+    // ? dead state, blocks of equivalent states are merged, and the block holding the
+    // ? dead state is dropped again so missing transitions still mean "reject".
+    pub fn minimize_hopcroft(&self) -> DFA {
+        let alphabet = self.get_alphabet();
+        let dead_state = self.state_count();
+        let total_states = dead_state + 1;
+
+        // ? complete[state][symbol_index], the dead state loops onto itself
+        let complete: Vec<Vec<usize>> = (0..total_states)
+            .map(|state| {
+                alphabet
+                    .iter()
+                    .map(|symbol| match self.transitions.get(state) {
+                        Some(transition) => *transition.get(symbol).unwrap_or(&dead_state),
+                        None => dead_state,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // ? inverse[symbol_index][state] = every state that moves into `state` on the symbol
+        let mut inverse: Vec<Vec<Vec<usize>>> =
+            vec![vec![Vec::new(); total_states]; alphabet.len()];
+        for (state, row) in complete.iter().enumerate() {
+            for (symbol_index, next_state) in row.iter().enumerate() {
+                inverse[symbol_index][*next_state].push(state);
+            }
+        }
+
+        // ? states start apart if they accept before different next chars, usually
+        // ? that is just accepting and rejecting
+        let mut initial: BTreeMap<(bool, Option<&Vec<Neighbour>>), Vec<usize>> = BTreeMap::new();
+        for state in 0..total_states {
+            let accepts = (
+                self.accepting_states.contains(&state),
+                self.look_accepting_states.get(&state),
+            );
+            initial.entry(accepts).or_default().push(state);
+        }
+
+        // ? Blocks are ranges of `elements`, `position` is where a state sits in it.
+        // ? Marking a state swaps it to the front of its block, so a split only
+        // ? moves the states on its smaller side and the work stays in proportion
+        // ? to the splitter, which is what gives Hopcroft's O(n log n).
+        let mut elements: Vec<usize> = Vec::with_capacity(total_states);
+        let mut position: Vec<usize> = vec![0; total_states];
+        let mut block_of: Vec<usize> = vec![0; total_states];
+        let mut block_start: Vec<usize> = Vec::new();
+        let mut block_end: Vec<usize> = Vec::new();
+        for block in initial.into_values().rev() {
+            block_start.push(elements.len());
+            for state in block {
+                position[state] = elements.len();
+                block_of[state] = block_start.len() - 1;
+                elements.push(state);
+            }
+            block_end.push(elements.len());
+        }
+        let mut marked: Vec<usize> = vec![0; block_start.len()];
+
+        // ? every block but the largest has to split the others at least once
+        let mut worklist: Vec<usize> = Vec::new();
+        let largest =
+            (0..block_start.len()).max_by_key(|block| block_end[*block] - block_start[*block]);
+        worklist.extend((0..block_start.len()).filter(|block| Some(*block) != largest));
+
+        while let Some(splitter) = worklist.pop() {
+            let splitter_states = elements[block_start[splitter]..block_end[splitter]].to_vec();
+
+            for predecessors in inverse.iter() {
+                // ? the DFA is complete, so no state moves into two splitter states
+                // ? on the same symbol and each is marked at most once
+                let mut touched: Vec<usize> = Vec::new();
+                for state in splitter_states.iter() {
+                    for prev_state in predecessors[*state].iter() {
+                        let block = block_of[*prev_state];
+                        let front = block_start[block] + marked[block];
+                        let other_state = elements[front];
+
+                        elements.swap(position[*prev_state], front);
+                        position[other_state] = position[*prev_state];
+                        position[*prev_state] = front;
+
+                        if marked[block] == 0 {
+                            touched.push(block);
+                        }
+                        marked[block] += 1;
+                    }
+                }
+
+                for block in touched {
+                    let (start, end) = (block_start[block], block_end[block]);
+                    let middle = start + std::mem::take(&mut marked[block]);
+                    if middle == end {
+                        continue;
+                    }
+
+                    // ? the smaller half becomes the new block
+                    let new_block = block_start.len();
+                    let (moved_start, moved_end) = if middle - start <= end - middle {
+                        block_start[block] = middle;
+                        (start, middle)
+                    } else {
+                        block_end[block] = middle;
+                        (middle, end)
+                    };
+                    for state in elements[moved_start..moved_end].iter() {
+                        block_of[*state] = new_block;
+                    }
+                    block_start.push(moved_start);
+                    block_end.push(moved_end);
+                    marked.push(0);
+
+                    // ? if the old block was pending both halves must be, otherwise the
+                    // ? smaller half is enough, either way that is the new block
+                    worklist.push(new_block);
+                }
+            }
+        }
+
+        let dead_block = block_of[dead_state];
+
+        // ? number the surviving blocks breadth first from the starts so output is stable
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        let mut transitions: Vec<BTreeMap<CharRange, usize>> = Vec::new();
+        let mut accepting_states: HashSet<usize> = HashSet::new();
+        let mut look_accepting_states: HashMap<usize, Vec<Neighbour>> = HashMap::new();
+
+        let start_states: Vec<usize> = self
+            .start_states
+            .iter()
+            .map(|start_state| {
+                let start_block = block_of[*start_state];
+                *ids.entry(start_block).or_insert_with(|| {
+                    queue.push_back(start_block);
+                    transitions.push(BTreeMap::new());
+                    transitions.len() - 1
+                })
+            })
+            .collect();
+
+        while let Some(block) = queue.pop_front() {
+            let id = ids[&block];
+            let representative = elements[block_start[block]];
+
+            if self.accepting_states.contains(&representative) {
+                accepting_states.insert(id);
+            }
+            if let Some(accepted) = self.look_accepting_states.get(&representative) {
+                look_accepting_states.insert(id, accepted.clone());
+            }
+            if block == dead_block {
+                continue;
+            }
+
+            for (symbol_index, symbol) in alphabet.iter().enumerate() {
+                let next_block = block_of[complete[representative][symbol_index]];
+                if next_block == dead_block {
+                    continue;
+                }
+
+                let next_id = match ids.get(&next_block) {
+                    Some(next_id) => *next_id,
+                    None => {
+                        let next_id = transitions.len();
+                        ids.insert(next_block, next_id);
+                        queue.push_back(next_block);
+                        transitions.push(BTreeMap::new());
+                        next_id
+                    }
+                };
+                transitions[id].insert(*symbol, next_id);
+            }
+        }
+
+        DFA {
+            start_state: start_states[Neighbour::Edge as usize],
+            start_states,
+            transitions,
+            accepting_states,
+            look_accepting_states,
+        }
+    }
+
+    // ? Brzozowski's algorithm: determinizing the reverse of a DFA gives a minimal DFA
+    // ? for the reversed language, so doing it twice gives the minimal DFA for ours.
+    // ? None for DFAs with assertions, reversing them can't tell what came next.
+    pub fn minimize_brzozowski(&self) -> Option<DFA> {
+        let has_looks = !self.look_accepting_states.is_empty()
+            || self
+                .start_states
+                .iter()
+                .any(|state| *state != self.start_state);
+        if has_looks {
+            return None;
+        }
+
+        let (edges, start_states, accepting_states) = self.reverse();
+        let reversed_dfa = DFA::determinize(&edges, &start_states, &accepting_states);
+
+        let (edges, start_states, accepting_states) = reversed_dfa.reverse();
+        Some(DFA::determinize(&edges, &start_states, &accepting_states))
+    }
+
+    // ? flips every transition, the accepting states become the start states and the
+    // ? start state becomes the only accepting one, which is an NFA without ε moves
+    fn reverse(&self) -> (NfaEdges, Vec<usize>, HashSet<usize>) {
+        let mut edges: NfaEdges = vec![BTreeMap::new(); self.state_count()];
+
+        for (state, transition) in self.transitions.iter().enumerate() {
+            for (symbol, next_state) in transition.iter() {
+                edges[*next_state].entry(*symbol).or_default().push(state);
+            }
+        }
+
+        (
+            edges,
+            self.get_accepting_states(),
+            HashSet::from([self.start_state]),
+        )
+    }
+
+    // ? subset construction over an ε-free NFA, numbered breadth first from the start
+    fn determinize(
+        edges: &[BTreeMap<CharRange, Vec<usize>>],
+        start_states: &[usize],
+        accepting_states: &HashSet<usize>,
+    ) -> DFA {
+        let mut alphabet: Vec<&CharRange> = edges
+            .iter()
+            .flat_map(|transition| transition.keys())
+            .collect::<HashSet<&CharRange>>()
+            .into_iter()
+            .collect();
+        alphabet.sort();
+
+        let mut start: Vec<usize> = start_states.to_vec();
+        start.sort();
+        start.dedup();
+
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut queue: VecDeque<Vec<usize>> = VecDeque::new();
+        let mut transitions: Vec<BTreeMap<CharRange, usize>> = Vec::new();
+        let mut dfa_accepting: HashSet<usize> = HashSet::new();
+
+        ids.insert(start.clone(), 0);
+        queue.push_back(start);
+        transitions.push(BTreeMap::new());
+
+        while let Some(curr_states) = queue.pop_front() {
+            let id = ids[&curr_states];
+
+            if curr_states
+                .iter()
+                .any(|state| accepting_states.contains(state))
+            {
+                dfa_accepting.insert(id);
+            }
+
+            for symbol in alphabet.iter() {
+                let mut next_states: Vec<usize> = curr_states
+                    .iter()
+                    .filter_map(|state| edges[*state].get(*symbol))
+                    .flatten()
+                    .copied()
+                    .collect();
+                next_states.sort();
+                next_states.dedup();
+
+                if next_states.is_empty() {
+                    continue;
+                }
+
+                let next_id = match ids.get(&next_states) {
+                    Some(next_id) => *next_id,
+                    None => {
+                        let next_id = transitions.len();
+                        ids.insert(next_states.clone(), next_id);
+                        queue.push_back(next_states);
+                        transitions.push(BTreeMap::new());
+                        next_id
+                    }
+                };
+                transitions[id].insert(**symbol, next_id);
+            }
+        }
+
+        DFA {
+            start_state: 0,
+            start_states: vec![0; Neighbour::ALL.len()],
+            transitions,
+            accepting_states: dfa_accepting,
+            look_accepting_states: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compiler::Compiler, dfa::DFA, parser::Parser};
+
+    fn get_dfa(pattern: &str) -> DFA {
+        DFA::from_nfa(&Compiler::compile(&Parser::parse(pattern).unwrap()))
+    }
+
+    #[test]
+    fn test_minimize_hopcroft_state_counts() {
+        let cases = [
+            ("(a|b)*abb", 5, 4),
+            ("a|b", 3, 2),
+            ("a*", 2, 1),
+            ("(a|b)*", 3, 1),
+            ("ab|cb", 5, 3),
+            ("abc", 4, 4),
+        ];
+
+        for (pattern, before, after) in cases {
+            let dfa = get_dfa(pattern);
+            let minimal_dfa = dfa.minimize_hopcroft();
+
+            assert_eq!(dfa.state_count(), before, "{}", pattern);
+            assert_eq!(minimal_dfa.state_count(), after, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_minimize_hopcroft_preserves_language() {
+        let inputs = [
+            "", "a", "b", "ab", "abb", "aabb", "babb", "abba", "cb", "abc",
+        ];
+
+        for pattern in [
+            "(a|b)*abb",
+            "a|b",
+            "ab|cb",
+            "(ab)*c*",
+            "a(b|c)*",
+            "[a-c]*b",
+            "(a|^b)*$",
+        ] {
+            let dfa = get_dfa(pattern);
+            let minimal_dfa = dfa.minimize_hopcroft();
+
+            for input in inputs {
+                assert_eq!(
+                    minimal_dfa.test(input),
+                    dfa.test(input),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimize_hopcroft_is_idempotent() {
+        let minimal_dfa = get_dfa("(a|b)*abb").minimize_hopcroft();
+
+        assert_eq!(minimal_dfa.minimize_hopcroft(), minimal_dfa);
+        assert_eq!(minimal_dfa.get_accepting_states(), vec![3]);
+    }
+
+    #[test]
+    fn test_minimize_hopcroft_long_chain() {
+        // ? every split peels one state off a block of thousands, so this is only
+        // ? quick if a split costs what the splitter touches, not the whole block
+        let dfa = get_dfa("(a|b)a{5000}");
+        let minimal_dfa = dfa.minimize_hopcroft();
+
+        assert_eq!(minimal_dfa.state_count(), 5002);
+        assert!(minimal_dfa.test(&format!("b{}", "a".repeat(5000))));
+        assert!(!minimal_dfa.test(&"a".repeat(5000)));
+    }
+
+    #[test]
+    fn test_minimize_brzozowski_matches_hopcroft() {
+        for pattern in [
+            "(a|b)*abb",
+            "a|b",
+            "a*",
+            "ab|cb",
+            "abc",
+            "(ab)*c*",
+            "a(b|c)*",
+            "[a-c]*b",
+            "(a|ab)(c|bcd)",
+        ] {
+            let dfa = get_dfa(pattern);
+            let hopcroft = dfa.minimize_hopcroft();
+            let brzozowski = dfa.minimize_brzozowski().unwrap();
+
+            assert_eq!(
+                brzozowski.state_count(),
+                hopcroft.state_count(),
+                "{}",
+                pattern
+            );
+            // ? both are numbered breadth first over a sorted alphabet, so the
+            // ? isomorphic results should come out identical
+            assert_eq!(brzozowski, hopcroft, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_minimize_brzozowski_rejects_assertions() {
+        for (pattern, input) in [("ab$", "ab"), ("^ab", "ab"), ("a\\b", "a")] {
+            let dfa = get_dfa(pattern);
+
+            assert!(dfa.minimize_hopcroft().test(input));
+            assert_eq!(dfa.minimize_brzozowski(), None, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_minimize_brzozowski_preserves_language() {
+        let inputs = [
+            "", "a", "b", "ab", "abb", "aabb", "babb", "abba", "cb", "abc",
+        ];
+
+        for pattern in ["(a|b)*abb", "ab|cb", "(ab)*c*", "a(b|c)*"] {
+            let dfa = get_dfa(pattern);
+            let minimal_dfa = dfa.minimize_brzozowski().unwrap();
+
+            for input in inputs {
+                assert_eq!(minimal_dfa.test(input), dfa.test(input));
+            }
+        }
+    }
+}
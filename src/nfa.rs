@@ -1,32 +1,46 @@
-use uuid::Uuid;
-
-use crate::state::{State, EPSILON};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-
+use crate::captures::Captures;
+use crate::look::Look;
+use crate::pikevm::PikeVM;
+use crate::state::{CharRange, State, StateId, Symbol};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+// ? NFA state -> one cell per symbol, ordered so printing is reproducible
+pub type NfaTable = BTreeMap<StateId, Vec<CELL>>;
+
+// ? The NFA owns every state in `states`, transitions are indices into it.
+// ? Combinators take their operands by value and move their states over,
+// ? so a finished machine is a single Vec with no shared ownership.
+// ? `group_names` is filled in by the compiler for the finished machine only,
+// ? combinators leave it empty.
+// ? Ids are relative to the machine's own arena, so `clone` is a real copy with
+// ? fresh states: a fragment can be cloned and reused in several places (counted
+// ? repetition, alternation branches) and every copy is wired separately once
+// ? `absorb` moves it into the bigger machine.
 #[derive(Clone, Debug)]
 pub struct NFA {
-    pub in_state: Rc<RefCell<State>>,
-    pub out_state: Rc<RefCell<State>>,
+    pub states: Vec<State>,
+    pub in_state: StateId,
+    pub out_state: StateId,
+    pub group_names: Arc<[Option<String>]>,
 }
 
 #[derive(Clone, Debug)]
 pub struct CELL {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub transition: Vec<String>,
 }
 
 impl CELL {
-    pub fn new(_symbol: &str) -> CELL {
+    pub fn new(symbol: Symbol) -> CELL {
         CELL {
-            symbol: _symbol.to_string(),
+            symbol,
             transition: Vec::new(),
         }
     }
     pub fn new_epslion() -> CELL {
         CELL {
-            symbol: EPSILON.to_string(),
+            symbol: Symbol::Epsilon,
             transition: Vec::new(),
         }
     }
@@ -35,158 +49,432 @@ impl CELL {
     }
 }
 
+impl Default for NFA {
+    fn default() -> Self {
+        NFA::new()
+    }
+}
+
 impl NFA {
     pub fn new() -> NFA {
         NFA {
-            in_state: Rc::new(RefCell::new(State::new(false))),
-            out_state: Rc::new(RefCell::new(State::new(true))),
+            states: vec![State::new(false), State::new(true)],
+            in_state: StateId(0),
+            out_state: StateId(1),
+            group_names: Arc::from([]),
+        }
+    }
+
+    pub fn state(&self, id: StateId) -> &State {
+        &self.states[id.index()]
+    }
+
+    pub fn state_mut(&mut self, id: StateId) -> &mut State {
+        &mut self.states[id.index()]
+    }
+
+    pub fn add_transition(&mut self, from: StateId, symbol: Symbol, to: StateId) {
+        self.state_mut(from).add_transition(symbol, to);
+    }
+
+    // ? moves every state of `other` to the end of this arena and returns
+    // ? the new ids of its in/out states
+    fn absorb(&mut self, other: NFA) -> (StateId, StateId) {
+        let offset = self.states.len() as u32;
+
+        for mut state in other.states {
+            state.shift_transitions(offset);
+            self.states.push(state);
+        }
+
+        (
+            StateId(other.in_state.0 + offset),
+            StateId(other.out_state.0 + offset),
+        )
+    }
+
+    pub fn char(symbol: char) -> NFA {
+        let mut nfa = NFA::new();
+        let (in_state, out_state) = (nfa.in_state, nfa.out_state);
+        nfa.state_mut(out_state).accepting = true;
+        nfa.add_transition(in_state, Symbol::char(symbol), out_state);
+        nfa
+    }
+
+    // ? one transition per range, a class doesn't need a state per char
+    pub fn class(ranges: &[CharRange]) -> NFA {
+        let mut nfa = NFA::new();
+        let (in_state, out_state) = (nfa.in_state, nfa.out_state);
+        for range in ranges {
+            nfa.add_transition(in_state, Symbol::Range(*range), out_state);
         }
+        nfa
     }
 
-    pub fn char(symbol: &str) -> NFA {
-        let nfa = NFA::new();
-        nfa.out_state.borrow_mut().accepting = true;
-        nfa.in_state
-            .borrow_mut()
-            .add_transition_for_symbol(symbol, nfa.out_state.clone());
+    // ? matches the empty string, but only where the assertion holds
+    pub fn look(look: Look) -> NFA {
+        let mut nfa = NFA::new();
+        let (in_state, out_state) = (nfa.in_state, nfa.out_state);
+        nfa.add_transition(in_state, Symbol::Look(look), out_state);
         nfa
     }
 
     pub fn add_epsilon_transition(&mut self) {
-        self.in_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, self.out_state.clone());
+        self.add_transition(self.in_state, Symbol::Epsilon, self.out_state);
     }
 
     pub fn test(&self, _string: &str) -> bool {
-        self.in_state.borrow().test(_string)
+        PikeVM::new(self).is_match(_string)
     }
 
-    pub fn concat_pair(first: &mut NFA, second: &mut NFA) -> NFA {
-        first.out_state.borrow_mut().accepting = false;
-        second.out_state.borrow_mut().accepting = true;
+    pub fn concat_pair(first: NFA, second: NFA) -> NFA {
+        let mut nfa = first;
+        let first_out = nfa.out_state;
+        let (second_in, second_out) = nfa.absorb(second);
 
-        first
-            .out_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, second.in_state.clone());
+        nfa.state_mut(first_out).accepting = false;
+        nfa.state_mut(second_out).accepting = true;
 
-        NFA {
-            in_state: first.in_state.clone(),
-            out_state: second.out_state.clone(),
-        }
+        nfa.add_transition(first_out, Symbol::Epsilon, second_in);
+        nfa.out_state = second_out;
+        nfa
     }
 
-    pub fn concat(first: &NFA, array_of_nfa: &[NFA]) -> NFA {
-        let mut current_nfa = first.clone();
+    pub fn concat(first: NFA, array_of_nfa: Vec<NFA>) -> NFA {
+        let mut current_nfa = first;
 
-        for nfa in array_of_nfa.iter() {
-            current_nfa = NFA::concat_pair(&mut current_nfa, &mut nfa.clone());
+        for nfa in array_of_nfa {
+            current_nfa = NFA::concat_pair(current_nfa, nfa);
         }
 
         current_nfa
     }
 
-    pub fn or_pair(first: &mut NFA, second: &mut NFA) -> NFA {
-        let final_nfa = NFA::new();
+    pub fn or_pair(first: NFA, second: NFA) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+
+        let (first_in, first_out) = final_nfa.absorb(first);
+        let (second_in, second_out) = final_nfa.absorb(second);
+
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+        final_nfa.add_transition(in_state, Symbol::Epsilon, second_in);
+
+        final_nfa.state_mut(first_out).accepting = false;
+        final_nfa.state_mut(second_out).accepting = false;
+
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
+        final_nfa.add_transition(second_out, Symbol::Epsilon, out_state);
+
+        final_nfa
+    }
+
+    pub fn rep(first: NFA) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+
+        let (first_in, first_out) = final_nfa.absorb(first);
+
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+        final_nfa.add_transition(in_state, Symbol::Epsilon, out_state);
+
+        final_nfa.state_mut(first_out).accepting = false;
+
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
+        final_nfa.add_transition(out_state, Symbol::Epsilon, first_in);
 
         final_nfa
-            .in_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, first.in_state.clone());
+    }
+
+    // ? like `rep` without the ε that skips the machine, so it runs at least once
+    pub fn plus(first: NFA) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+
+        let (first_in, first_out) = final_nfa.absorb(first);
+
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+
+        final_nfa.state_mut(first_out).accepting = false;
+
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
+        final_nfa.add_transition(out_state, Symbol::Epsilon, first_in);
 
         final_nfa
-            .in_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, second.in_state.clone());
+    }
+
+    // ? like `rep` without the ε that loops back, so it runs at most once
+    pub fn optional(first: NFA) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+
+        let (first_in, first_out) = final_nfa.absorb(first);
 
-        first.out_state.borrow_mut().accepting = false;
-        second.out_state.borrow_mut().accepting = false;
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+        final_nfa.add_transition(in_state, Symbol::Epsilon, out_state);
 
-        first
-            .out_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, final_nfa.out_state.clone());
+        final_nfa.state_mut(first_out).accepting = false;
 
-        second
-            .out_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, final_nfa.out_state.clone());
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
 
         final_nfa
     }
 
-    pub fn rep(first: &mut NFA) -> NFA {
-        let final_nfa = NFA::new();
+    // ? The lazy loops try leaving before going (again) through the machine: the
+    // ? ε that skips or ends it is added before the one that enters it, and that
+    // ? order is thread priority. The loop back starts at the machine's own out
+    // ? state, since the ε to whatever follows is only added to `out_state` later.
+    pub fn lazy_rep(first: NFA) -> NFA {
+        NFA::lazy_loop(first, true, true)
+    }
+
+    pub fn lazy_plus(first: NFA) -> NFA {
+        NFA::lazy_loop(first, false, true)
+    }
+
+    pub fn lazy_optional(first: NFA) -> NFA {
+        NFA::lazy_loop(first, true, false)
+    }
+
+    fn lazy_loop(first: NFA, can_skip: bool, can_repeat: bool) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+
+        let (first_in, first_out) = final_nfa.absorb(first);
+
+        if can_skip {
+            final_nfa.add_transition(in_state, Symbol::Epsilon, out_state);
+        }
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+
+        final_nfa.state_mut(first_out).accepting = false;
+
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
+        if can_repeat {
+            final_nfa.add_transition(first_out, Symbol::Epsilon, first_in);
+        }
 
         final_nfa
-            .in_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, first.in_state.clone());
+    }
+
+    // ? Counted repetition by copying the machine: `min` required copies, then
+    // ? `max - min` optional ones as in x(x(x)?)? so a failed copy ends the run,
+    // ? or with no `max` a last required copy that loops. Lazy repetition uses
+    // ? the lazy loops, so it stops after as few copies as it can.
+    pub fn repeat(first: NFA, min: usize, max: Option<usize>, greedy: bool) -> NFA {
+        let (required, tail) = match max {
+            None if min == 0 && greedy => return NFA::rep(first),
+            None if min == 0 => return NFA::lazy_rep(first),
+            None if greedy => (min - 1, Some(NFA::plus(first.clone()))),
+            None => (min - 1, Some(NFA::lazy_plus(first.clone()))),
+            Some(max) if max > min => (min, Some(NFA::optional_copies(&first, max - min, greedy))),
+            Some(_) => (min, None),
+        };
+
+        let mut array_of_nfa: Vec<NFA> = vec![first; required];
+        array_of_nfa.extend(tail);
+
+        if array_of_nfa.is_empty() {
+            let mut nfa = NFA::new();
+            nfa.add_epsilon_transition();
+            return nfa;
+        }
+        let first = array_of_nfa.remove(0);
+        NFA::concat(first, array_of_nfa)
+    }
 
+    // ? The optional copies of x(x(x)?)? in a single arena. Leaving the nested
+    // ? optionals early ends up at the outermost out state through ε alone, so
+    // ? every copy skips straight there and a copy is appended without moving the
+    // ? ones before it again.
+    fn optional_copies(first: &NFA, count: usize, greedy: bool) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
+        let mut curr_state = in_state;
+
+        for _ in 0..count {
+            let (first_in, first_out) = final_nfa.absorb(first.clone());
+            final_nfa.state_mut(first_out).accepting = false;
+
+            if greedy {
+                final_nfa.add_transition(curr_state, Symbol::Epsilon, first_in);
+                final_nfa.add_transition(curr_state, Symbol::Epsilon, out_state);
+            } else {
+                final_nfa.add_transition(curr_state, Symbol::Epsilon, out_state);
+                final_nfa.add_transition(curr_state, Symbol::Epsilon, first_in);
+            }
+            curr_state = first_out;
+        }
+
+        final_nfa.add_transition(curr_state, Symbol::Epsilon, out_state);
         final_nfa
-            .in_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, final_nfa.out_state.clone());
+    }
 
-        first.out_state.borrow_mut().accepting = false;
+    // ? capture group `index` owns slots 2 * index (start) and 2 * index + 1 (end),
+    // ? the wrapper states are ε-only so matching and the DFA are unaffected
+    pub fn group(first: NFA, index: usize) -> NFA {
+        let mut final_nfa = NFA::new();
+        let (in_state, out_state) = (final_nfa.in_state, final_nfa.out_state);
 
-        first
-            .out_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, final_nfa.out_state.clone());
+        let (first_in, first_out) = final_nfa.absorb(first);
+
+        final_nfa.state_mut(in_state).capture = Some(index * 2);
+        final_nfa.state_mut(out_state).capture = Some(index * 2 + 1);
+
+        final_nfa.add_transition(in_state, Symbol::Epsilon, first_in);
+        final_nfa.state_mut(first_out).accepting = false;
+        final_nfa.add_transition(first_out, Symbol::Epsilon, out_state);
 
         final_nfa
-            .out_state
-            .borrow_mut()
-            .add_transition_for_symbol(EPSILON, first.in_state.clone());
+    }
+
+    // ? A compiled machine has one name entry per group of the pattern, so that
+    // ? counts even the groups `{0}` dropped along with their operand. Fragments
+    // ? have no names and fall back to the highest capture slot left in the arena.
+    pub fn group_count(&self) -> usize {
+        let slot_count = self
+            .states
+            .iter()
+            .filter_map(|state| state.capture)
+            .max()
+            .map_or(0, |slot| slot / 2 + 1);
+        slot_count.max(self.group_names.len())
+    }
+
+    // ? Every transition flipped, the old out state is the new in state and the old
+    // ? in state the only accepting one, so the machine reads matches backwards.
+    // ? Assertions are flipped too, the start of the text is the end backwards.
+    // ? Capture slots don't mean anything backwards and are left out.
+    pub fn reverse(&self) -> NFA {
+        let mut states: Vec<State> = self.states.iter().map(|_| State::new(false)).collect();
+
+        for (index, state) in self.states.iter().enumerate() {
+            for symbol in state.get_all_transition_symbols() {
+                for next_state in state.get_transitions(symbol) {
+                    states[next_state.index()]
+                        .add_transition(symbol.reversed(), StateId(index as u32));
+                }
+            }
+        }
+        states[self.in_state.index()].accepting = true;
+
+        NFA {
+            states,
+            in_state: self.out_state,
+            out_state: self.in_state,
+            group_names: Arc::from([]),
+        }
+    }
+
+    // ? one entry per group in index order, group 0 and unnamed groups are None
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        (0..self.group_count()).map(|index| self.group_names.get(index)?.as_deref())
+    }
+
+    pub fn captures<'h>(&self, _string: &'h str) -> Option<Captures<'h>> {
+        PikeVM::new(self).captures(_string)
+    }
+
+    // ? DFA Traversal
+
+    pub fn count_unique_transitions(&self) -> (u64, BTreeSet<Symbol>, BTreeSet<StateId>, StateId) {
+        let mut stack: Vec<StateId> = vec![self.in_state];
+        let mut is_visited: HashMap<StateId, bool> = HashMap::new();
+        let mut count: u64 = 0;
+
+        let mut all_transition_symbols: BTreeSet<Symbol> = BTreeSet::new();
+        let mut all_state_ids: BTreeSet<StateId> = BTreeSet::new();
+
+        let mut accepting_state_id: StateId = self.in_state;
+
+        while let Some(curr_id) = stack.pop() {
+            if is_visited.get(&curr_id) == Some(&true) {
+                continue;
+            }
+
+            let curr_state = self.state(curr_id);
+            all_state_ids.insert(curr_id);
+
+            if curr_state.accepting {
+                accepting_state_id = curr_id;
+            }
+
+            count += 1;
+            is_visited.insert(curr_id, true);
+
+            for symbol in curr_state.get_all_transition_symbols() {
+                all_transition_symbols.insert(symbol);
+                for next_state in curr_state.get_transitions(symbol) {
+                    if is_visited.get(next_state) != Some(&true) {
+                        stack.push(*next_state);
+                    }
+                }
+            }
+        }
 
-        return final_nfa;
+        (
+            count,
+            all_transition_symbols,
+            all_state_ids,
+            accepting_state_id,
+        )
     }
 
-    pub fn print_transition_table(transition_map: &HashMap<Uuid, Vec<CELL>>) {
-        println!("{:<36} {:<10} {:<10}", "State", "Symbol", "Transitions");
+    // EPSILON
+    pub fn epslion_closure(&self, state_id: StateId) -> Vec<StateId> {
+        let mut epsilon_vector: Vec<StateId> = vec![state_id];
+        epsilon_vector.extend(self.state(state_id).get_transitions(Symbol::Epsilon));
+        epsilon_vector
+    }
+
+    // ? rows come out ordered by state id and symbol, so the output is stable run to run
+    pub fn format_transition_table(transition_map: &NfaTable) -> String {
+        let mut table = format!("{:<8} {:<10} {:<10}\n", "State", "Symbol", "Transitions");
 
         for (state, cells) in transition_map {
             let state_str = state.to_string();
             for cell in cells {
                 let transitions_str = cell.transition.join(", ");
-                println!(
-                    "{:<36} {:<10} {:<10}",
-                    state_str, cell.symbol, transitions_str
-                );
+                table.push_str(&format!(
+                    "{:<8} {:<10} {:<10}\n",
+                    state_str,
+                    cell.symbol.to_string(),
+                    transitions_str
+                ));
             }
         }
+        table
     }
 
-    pub fn get_transition_table(&self) -> (HashMap<Uuid, Vec<CELL>>, Uuid, Vec<CELL>) {
-        let mut transition_table: HashMap<Uuid, Vec<CELL>> = HashMap::new();
+    pub fn print_transition_table(transition_map: &NfaTable) {
+        print!("{}", NFA::format_transition_table(transition_map));
+    }
+
+    pub fn get_transition_table(&self) -> (NfaTable, StateId, Vec<CELL>) {
+        let mut transition_table: NfaTable = BTreeMap::new();
 
-        let (_no_of_node, all_unique_transition, all_unique_uuid, state_map, accepting_state_uuid) =
-            self.in_state.borrow().count_unique_transitions();
+        let (_no_of_node, all_unique_transition, all_state_ids, accepting_state_id) =
+            self.count_unique_transitions();
 
-        for curr_id in all_unique_uuid.iter() {
+        for curr_id in all_state_ids.iter() {
             let mut array_of_cell: Vec<CELL> = Vec::new();
-            for curr_str in all_unique_transition.iter() {
-                let new_cell = CELL::new(&curr_str);
+            for symbol in all_unique_transition.iter() {
+                let new_cell = CELL::new(*symbol);
                 array_of_cell.push(new_cell);
             }
 
             transition_table.insert(*curr_id, array_of_cell);
         }
 
-        for curr_id in all_unique_uuid.iter() {
-            if let Some(state_rc) = state_map.get(curr_id) {
-                let state = state_rc;
-
-                for symbol in state.get_all_transition_symbols() {
-                    if let Some(cells) = transition_table.get_mut(curr_id) {
-                        for cell in cells.iter_mut() {
-                            if cell.symbol == symbol {
-                                let next_states = state.get_transition_for_symbol(&symbol);
-                                for next_state in next_states {
-                                    cell.add_transition(&next_state.borrow().label.to_string());
-                                }
+        for curr_id in all_state_ids.iter() {
+            let state = self.state(*curr_id);
+
+            for symbol in state.get_all_transition_symbols() {
+                if let Some(cells) = transition_table.get_mut(curr_id) {
+                    for cell in cells.iter_mut() {
+                        if cell.symbol == symbol {
+                            for next_state in state.get_transitions(symbol) {
+                                cell.add_transition(&next_state.to_string());
                             }
                         }
                     }
@@ -196,10 +484,10 @@ impl NFA {
 
         let mut all_e_transitions: Vec<CELL> = Vec::new();
 
-        for curr_id in all_unique_uuid.iter() {
+        for curr_id in all_state_ids.iter() {
             if let Some(cells) = transition_table.get_mut(curr_id) {
                 for cell in cells.iter_mut() {
-                    if cell.symbol == EPSILON {
+                    if cell.symbol == Symbol::Epsilon {
                         cell.add_transition(&curr_id.to_string());
                         all_e_transitions.push(cell.clone());
                     }
@@ -207,8 +495,7 @@ impl NFA {
             }
         }
 
-        NFA::print_transition_table(&transition_table);
-        return (transition_table, accepting_state_uuid, all_e_transitions);
+        (transition_table, accepting_state_id, all_e_transitions)
     }
 }
 
@@ -216,18 +503,21 @@ impl NFA {
 mod test {
 
     use super::*;
-    use crate::state::EPSILON;
-    use std::rc::Rc;
+
+    fn assert_machine(nfa: &NFA, accepting: &[bool]) {
+        let actual: Vec<bool> = nfa.states.iter().map(|state| state.accepting).collect();
+        assert_eq!(actual, accepting);
+    }
 
     #[test]
     fn test_char() {
-        let nfa = NFA::char("a");
-        assert_eq!(nfa.in_state.borrow_mut().accepting, false);
-        assert_eq!(nfa.out_state.borrow_mut().accepting, true);
+        let nfa = NFA::char('a');
+        assert!(!nfa.state(nfa.in_state).accepting);
+        assert!(nfa.state(nfa.out_state).accepting);
 
-        let first_transition = nfa.in_state.borrow_mut().get_transition_for_symbol("a");
+        let first_transition = nfa.state(nfa.in_state).get_transitions_for_char('a');
 
-        assert!(Rc::ptr_eq(&first_transition[0], &nfa.out_state));
+        assert_eq!(first_transition[0], nfa.out_state);
         assert_eq!(first_transition.len(), 1);
     }
 
@@ -236,190 +526,327 @@ mod test {
         let mut nfa = NFA::new();
         nfa.add_epsilon_transition();
 
-        assert_eq!(nfa.in_state.borrow_mut().accepting, false);
-        assert_eq!(nfa.out_state.borrow_mut().accepting, true);
+        assert!(!nfa.state(nfa.in_state).accepting);
+        assert!(nfa.state(nfa.out_state).accepting);
 
-        let first_transition = nfa.in_state.borrow_mut().get_transition_for_symbol(EPSILON);
+        let first_transition = nfa.state(nfa.in_state).get_transitions(Symbol::Epsilon);
 
-        assert!(Rc::ptr_eq(&first_transition[0], &nfa.out_state));
+        assert_eq!(first_transition[0], nfa.out_state);
         assert_eq!(first_transition.len(), 1);
     }
 
     #[test]
     fn test_concat_pair() {
-        let mut first = NFA::char("a");
-        let mut second = NFA::char("b");
+        let first = NFA::char('a');
+        let second = NFA::char('b');
 
-        let combine_transition = NFA::concat_pair(&mut first, &mut second);
+        let combine_transition = NFA::concat_pair(first, second);
 
-        assert_eq!(first.in_state.borrow_mut().accepting, false);
-        assert_eq!(first.out_state.borrow_mut().accepting, false);
+        // ? first machine keeps ids 0 and 1, second one is moved to 2 and 3
+        let (first_in, first_out) = (StateId(0), StateId(1));
+        let (second_in, second_out) = (StateId(2), StateId(3));
 
-        assert_eq!(second.in_state.borrow_mut().accepting, false);
-        assert_eq!(second.out_state.borrow_mut().accepting, true);
+        assert_machine(&combine_transition, &[false, false, false, true]);
+        assert_eq!(combine_transition.in_state, first_in);
+        assert_eq!(combine_transition.out_state, second_out);
 
-        assert_eq!(combine_transition.in_state.borrow_mut().accepting, false);
-        assert_eq!(combine_transition.out_state.borrow_mut().accepting, true);
+        let first_transition = combine_transition
+            .state(first_in)
+            .get_transitions_for_char('a');
 
-        let first_transition = first.in_state.borrow_mut().get_transition_for_symbol("a");
+        assert_eq!(first_transition, vec![first_out]);
 
-        assert!(Rc::ptr_eq(&first_transition[0], &first.out_state));
-        assert_eq!(first_transition.len(), 1);
-
-        let epsilon_transition = first
-            .out_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
-        assert_eq!(epsilon_transition.len(), 1);
-
-        let second_transition = second.in_state.borrow_mut().get_transition_for_symbol("b");
-        assert_eq!(second_transition.len(), 1);
+        let epsilon_transition = combine_transition
+            .state(first_out)
+            .get_transitions(Symbol::Epsilon);
+        assert_eq!(epsilon_transition, vec![second_in]);
 
-        // ? checking ptr location because RC allows multiple ownership fk rust
-        assert!(Rc::ptr_eq(&second_transition[0], &second.out_state));
+        let second_transition = combine_transition
+            .state(second_in)
+            .get_transitions_for_char('b');
+        assert_eq!(second_transition, vec![second_out]);
     }
+
     #[test]
     fn test_concat() {
-        let mut first = NFA::char("a");
-        let second = NFA::char("b");
-        let third = NFA::char("c");
+        let first = NFA::char('a');
+        let second = NFA::char('b');
+        let third = NFA::char('c');
 
-        let mut array_of_nfa = vec![second.clone(), third.clone()];
+        let array_of_nfa = vec![second, third];
 
-        let final_nfa = NFA::concat(&mut first, &mut array_of_nfa);
+        let final_nfa = NFA::concat(first, array_of_nfa);
 
-        assert_eq!(final_nfa.in_state.borrow_mut().accepting, false);
-        assert_eq!(final_nfa.out_state.borrow_mut().accepting, true);
+        assert_eq!(final_nfa.in_state, StateId(0));
+        assert_eq!(final_nfa.out_state, StateId(5));
+        assert_machine(&final_nfa, &[false, false, false, false, false, true]);
 
-        assert_eq!(first.in_state.borrow_mut().accepting, false);
-        assert_eq!(first.out_state.borrow_mut().accepting, false);
+        let first_transition = final_nfa.state(StateId(0)).get_transitions_for_char('a');
+        assert_eq!(first_transition, vec![StateId(1)]);
 
-        assert_eq!(second.in_state.borrow_mut().accepting, false);
-        assert_eq!(second.out_state.borrow_mut().accepting, false);
+        let epsilon_transition_first = final_nfa.state(StateId(1)).get_transitions(Symbol::Epsilon);
+        assert_eq!(epsilon_transition_first, vec![StateId(2)]);
 
-        assert_eq!(third.in_state.borrow_mut().accepting, false);
-        assert_eq!(third.out_state.borrow_mut().accepting, true);
+        let second_transition = final_nfa.state(StateId(2)).get_transitions_for_char('b');
+        assert_eq!(second_transition, vec![StateId(3)]);
 
-        let first_transition = first.in_state.borrow_mut().get_transition_for_symbol("a");
-        assert_eq!(first_transition.len(), 1);
-        assert!(Rc::ptr_eq(&first_transition[0], &first.out_state));
+        let epsilon_transition_second =
+            final_nfa.state(StateId(3)).get_transitions(Symbol::Epsilon);
+        assert_eq!(epsilon_transition_second, vec![StateId(4)]);
+
+        let third_transition = final_nfa.state(StateId(4)).get_transitions_for_char('c');
+        assert_eq!(third_transition, vec![StateId(5)]);
+    }
+
+    #[test]
+    fn test_or_pair() {
+        let first = NFA::char('a');
+        let second = NFA::char('b');
+        let final_nfa = NFA::or_pair(first, second);
 
-        let epsilon_transition_first = first
-            .out_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
-        assert_eq!(epsilon_transition_first.len(), 1);
-        assert!(Rc::ptr_eq(&epsilon_transition_first[0], &second.in_state));
+        // ? own in/out first, then the first (2, 3) and second (4, 5) machines
+        assert_machine(&final_nfa, &[false, true, false, false, false, false]);
+        assert_eq!(final_nfa.in_state, StateId(0));
+        assert_eq!(final_nfa.out_state, StateId(1));
 
-        let second_transition = second.in_state.borrow_mut().get_transition_for_symbol("b");
-        assert_eq!(second_transition.len(), 1);
-        assert!(Rc::ptr_eq(&second_transition[0], &second.out_state));
+        let epsilon_transit = final_nfa
+            .state(final_nfa.in_state)
+            .get_transitions(Symbol::Epsilon);
+        assert_eq!(epsilon_transit, vec![StateId(2), StateId(4)]);
 
-        let epsilon_transition_second = second
-            .out_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
-        assert_eq!(epsilon_transition_second.len(), 1);
-        assert!(Rc::ptr_eq(&epsilon_transition_second[0], &third.in_state));
+        let first_out_transit = final_nfa.state(StateId(3)).get_transitions(Symbol::Epsilon);
+        assert_eq!(first_out_transit, vec![final_nfa.out_state]);
 
-        let third_transition = third.in_state.borrow_mut().get_transition_for_symbol("c");
-        assert_eq!(third_transition.len(), 1);
-        assert!(Rc::ptr_eq(&third_transition[0], &third.out_state));
+        let second_out_transit = final_nfa.state(StateId(5)).get_transitions(Symbol::Epsilon);
+        assert_eq!(second_out_transit, vec![final_nfa.out_state]);
     }
 
     #[test]
-    fn test_or_pair() {
-        let mut first = NFA::char("a");
-        let mut second = NFA::char("b");
-        let final_nfa = NFA::or_pair(&mut first, &mut second);
+    fn test_or_rep() {
+        let a_state_machine = NFA::char('a');
 
-        assert_eq!(final_nfa.in_state.borrow().accepting, false);
-        assert_eq!(final_nfa.out_state.borrow().accepting, true);
+        let final_nfa = NFA::rep(a_state_machine);
+        let (a_in, a_out) = (StateId(2), StateId(3));
 
-        assert_eq!(first.in_state.borrow().accepting, false);
-        assert_eq!(first.out_state.borrow().accepting, false);
+        assert_machine(&final_nfa, &[false, true, false, false]);
 
-        assert_eq!(second.in_state.borrow().accepting, false);
-        assert_eq!(second.out_state.borrow().accepting, false);
+        let first_transitions = final_nfa
+            .state(final_nfa.in_state)
+            .get_transitions(Symbol::Epsilon);
 
-        let epsilon_transit = final_nfa
-            .in_state
-            .borrow()
-            .get_transition_for_symbol(EPSILON);
-        assert_eq!(epsilon_transit.len(), 2);
-        assert!(Rc::ptr_eq(&epsilon_transit[0], &first.in_state));
-        assert!(Rc::ptr_eq(&epsilon_transit[1], &second.in_state));
+        // ? final in_state -> final out_state with elision transition
+        assert_eq!(first_transitions, vec![a_in, final_nfa.out_state]);
+
+        let second_transition = final_nfa.state(a_in).get_transitions_for_char('a');
+        assert_eq!(second_transition, vec![a_out]);
 
-        let first_out_transit = first.out_state.borrow().get_transition_for_symbol(EPSILON);
-        assert_eq!(first_out_transit.len(), 1);
-        assert!(Rc::ptr_eq(&first_out_transit[0], &final_nfa.out_state));
+        let third_transition = final_nfa.state(a_out).get_transitions(Symbol::Epsilon);
+        assert_eq!(third_transition, vec![final_nfa.out_state]);
 
-        let second_out_transit = second.out_state.borrow().get_transition_for_symbol(EPSILON);
-        assert_eq!(second_out_transit.len(), 1);
-        assert!(Rc::ptr_eq(&second_out_transit[0], &final_nfa.out_state));
+        let fourth_transition = final_nfa
+            .state(final_nfa.out_state)
+            .get_transitions(Symbol::Epsilon);
+        assert_eq!(fourth_transition, vec![a_in]);
     }
 
     #[test]
-    fn test_or_rep() {
-        let mut a_state_machine = NFA::char("a");
+    fn test_group() {
+        let final_nfa = NFA::group(NFA::char('a'), 1);
+        let (a_in, a_out) = (StateId(2), StateId(3));
 
-        let final_nfa = NFA::rep(&mut a_state_machine);
+        assert_machine(&final_nfa, &[false, true, false, false]);
+        assert_eq!(final_nfa.state(final_nfa.in_state).capture, Some(2));
+        assert_eq!(final_nfa.state(final_nfa.out_state).capture, Some(3));
+        assert_eq!(final_nfa.state(a_in).capture, None);
 
-        assert_eq!(final_nfa.in_state.borrow_mut().accepting, false);
-        assert_eq!(final_nfa.out_state.borrow_mut().accepting, true);
+        let first_transitions = final_nfa
+            .state(final_nfa.in_state)
+            .get_transitions(Symbol::Epsilon);
+        assert_eq!(first_transitions, vec![a_in]);
 
-        assert_eq!(a_state_machine.in_state.borrow_mut().accepting, false);
-        assert_eq!(a_state_machine.out_state.borrow_mut().accepting, false);
+        let last_transitions = final_nfa.state(a_out).get_transitions(Symbol::Epsilon);
+        assert_eq!(last_transitions, vec![final_nfa.out_state]);
 
-        let first_transitions = final_nfa
-            .in_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
+        assert_eq!(final_nfa.group_count(), 2);
+        assert_eq!(NFA::char('a').group_count(), 0);
+    }
 
-        assert_eq!(first_transitions.len(), 2);
-        assert!(Rc::ptr_eq(&first_transitions[0], &a_state_machine.in_state));
+    #[test]
+    fn test_reverse() {
+        let nfa = NFA::concat_pair(NFA::char('a'), NFA::char('b'));
+        let reversed = nfa.reverse();
+
+        assert_machine(&reversed, &[true, false, false, false]);
+        assert_eq!(reversed.in_state, nfa.out_state);
+        assert_eq!(reversed.out_state, nfa.in_state);
+        assert_eq!(
+            reversed.state(StateId(3)).get_transitions_for_char('b'),
+            vec![StateId(2)]
+        );
+        assert_eq!(
+            reversed.state(StateId(2)).get_transitions(Symbol::Epsilon),
+            vec![StateId(1)]
+        );
+
+        assert!(reversed.test("ba"));
+        assert!(!reversed.test("ab"));
+        assert!(NFA::rep(nfa).reverse().test("bababa"));
+    }
 
-        // ? final in_state -> final out_state with elision transition
-        assert!(Rc::ptr_eq(
-            &first_transitions[1],
-            &final_nfa.out_state.clone()
-        ));
-
-        let second_transition = a_state_machine
-            .in_state
-            .borrow_mut()
-            .get_transition_for_symbol("a");
-
-        assert!(Rc::ptr_eq(
-            &second_transition[0],
-            &a_state_machine.out_state
-        ));
-        assert_eq!(second_transition.len(), 1);
-
-        let third_transition = a_state_machine
-            .out_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
-
-        assert_eq!(third_transition.len(), 1);
-        assert!(Rc::ptr_eq(&third_transition[0], &final_nfa.out_state));
+    #[test]
+    fn test_class() {
+        let nfa = NFA::class(&[CharRange::new('a', 'z'), CharRange::single('_')]);
+
+        assert_machine(&nfa, &[false, true]);
+        assert_eq!(nfa.state(nfa.in_state).transitions.len(), 2);
+        assert!(nfa.test("q"));
+        assert!(nfa.test("_"));
+        assert!(!nfa.test("A"));
+        assert!(!nfa.test("ab"));
+        assert!(!NFA::class(&[]).test("a"));
+    }
 
-        let fourth_transition = final_nfa
-            .out_state
-            .borrow_mut()
-            .get_transition_for_symbol(EPSILON);
+    #[test]
+    fn test_plus_and_optional() {
+        let plus = NFA::plus(NFA::char('a'));
+        assert!(!plus.test(""));
+        assert!(plus.test("a"));
+        assert!(plus.test("aaa"));
+
+        let optional = NFA::optional(NFA::char('a'));
+        assert!(optional.test(""));
+        assert!(optional.test("a"));
+        assert!(!optional.test("aa"));
+    }
 
-        assert_eq!(fourth_transition.len(), 1);
-        assert!(Rc::ptr_eq(&fourth_transition[0], &a_state_machine.in_state));
+    #[test]
+    fn test_repeat() {
+        let counted = |min, max| {
+            NFA::repeat(
+                NFA::concat_pair(NFA::char('a'), NFA::char('b')),
+                min,
+                max,
+                true,
+            )
+        };
+
+        for (min, max, accepted) in [
+            (2, Some(2), vec![2]),
+            (1, Some(3), vec![1, 2, 3]),
+            (0, Some(1), vec![0, 1]),
+            (0, Some(0), vec![0]),
+            (2, None, vec![2, 3, 4]),
+            (0, None, vec![0, 1, 2, 3, 4]),
+        ] {
+            let nfa = counted(min, max);
+            for count in 0..5 {
+                assert_eq!(
+                    nfa.test(&"ab".repeat(count)),
+                    accepted.contains(&count),
+                    "{{{},{:?}}} on {}",
+                    min,
+                    max,
+                    count
+                );
+            }
+        }
+
+        // ? the copies are separate states, not the same ones wired twice
+        let nfa = counted(3, Some(3));
+        assert_eq!(nfa.states.len(), 12);
+    }
+
+    #[test]
+    fn test_clone_is_a_separate_copy() {
+        let fragment = NFA::concat_pair(NFA::char('a'), NFA::char('b'));
+
+        // ? the same fragment on both sides of a concat and an alternation
+        let twice = NFA::concat(fragment.clone(), vec![fragment.clone()]);
+        assert_eq!(twice.states.len(), 2 * fragment.states.len());
+        assert!(twice.test("abab"));
+        assert!(!twice.test("ab"));
+
+        let either = NFA::or_pair(fragment.clone(), fragment.clone());
+        assert!(either.test("ab"));
+        assert!(!either.test("abab"));
+
+        // ? no target of the copy points back into the original's states
+        for (index, state) in twice.states.iter().enumerate().skip(fragment.states.len()) {
+            let targets = state
+                .transitions
+                .iter()
+                .flat_map(|(_, next_states)| next_states)
+                .chain(state.epsilon_transitions.iter());
+            for next_state in targets {
+                assert!(next_state.index() >= fragment.states.len(), "q{}", index);
+            }
+        }
+
+        // ? changing the copy leaves the original alone
+        let mut copy = fragment.clone();
+        copy.add_transition(copy.out_state, Symbol::Epsilon, copy.in_state);
+        assert!(copy.test("abab"));
+        assert!(!fragment.test("abab"));
+    }
+
+    #[test]
+    fn test_nfa_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NFA>();
     }
 
     #[test]
     fn test_get_transition_table() {
-        let mut nfa_1 = NFA::char("a");
-        let mut nfa_2 = NFA::char("b");
-        let or_machine_nfa = NFA::or_pair(&mut nfa_1, &mut nfa_2);
+        let nfa_1 = NFA::char('a');
+        let nfa_2 = NFA::char('b');
+        let or_machine_nfa = NFA::or_pair(nfa_1, nfa_2);
         let transition_table = or_machine_nfa.get_transition_table();
+        NFA::print_transition_table(&transition_table.0);
         dbg!("accepting state", transition_table.1);
         assert_eq!(transition_table.0.len(), 6);
     }
+
+    #[test]
+    fn test_format_transition_table_is_reproducible() {
+        let build = || NFA::rep(NFA::char('a'));
+        let transition_table = build().get_transition_table();
+
+        assert_eq!(transition_table.1, StateId(1));
+        assert_eq!(
+            NFA::format_transition_table(&transition_table.0),
+            NFA::format_transition_table(&build().get_transition_table().0)
+        );
+        assert_eq!(
+            NFA::format_transition_table(&transition_table.0),
+            [
+                "State    Symbol     Transitions",
+                "q0       a                    ",
+                "q0       ε          q2, q1, q0",
+                "q1       a                    ",
+                "q1       ε          q2, q1    ",
+                "q2       a          q3        ",
+                "q2       ε          q2        ",
+                "q3       a                    ",
+                "q3       ε          q1, q3    ",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_transition_table_labels_ranges() {
+        let nfa = NFA::or_pair(NFA::class(&[CharRange::new('a', 'z')]), NFA::char('_'));
+        let (transition_table, _, _) = nfa.get_transition_table();
+
+        let symbols: BTreeSet<String> = transition_table
+            .values()
+            .flatten()
+            .map(|cell| cell.symbol.to_string())
+            .collect();
+        assert_eq!(
+            symbols,
+            BTreeSet::from(["_".to_string(), "a-z".to_string(), "ε".to_string()])
+        );
+    }
 }
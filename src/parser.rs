@@ -0,0 +1,660 @@
+use crate::ast::{Anchor, Ast, ClassRange};
+use crate::error::{RegexError, Span};
+
+pub const ALTERNATION: char = '|';
+pub const KLEENE_STAR: char = '*';
+pub const ONE_OR_MORE: char = '+';
+pub const ZERO_OR_ONE: char = '?';
+pub const REPEAT_OPEN: char = '{';
+pub const REPEAT_CLOSE: char = '}';
+pub const REPEAT_SEPARATOR: char = ',';
+pub const GROUP_OPEN: char = '(';
+pub const GROUP_CLOSE: char = ')';
+pub const CLASS_OPEN: char = '[';
+pub const CLASS_CLOSE: char = ']';
+pub const CLASS_RANGE: char = '-';
+pub const CLASS_NEGATE: char = '^';
+pub const START_ANCHOR: char = '^';
+pub const END_ANCHOR: char = '$';
+pub const ESCAPE: char = '\\';
+pub const GROUP_KIND: char = '?';
+pub const GROUP_NAME_OPEN: char = '<';
+pub const GROUP_NAME_CLOSE: char = '>';
+
+pub fn is_meta_character(symbol: char) -> bool {
+    matches!(
+        symbol,
+        ALTERNATION
+            | KLEENE_STAR
+            | ONE_OR_MORE
+            | REPEAT_OPEN
+            | REPEAT_CLOSE
+            | GROUP_OPEN
+            | GROUP_CLOSE
+            | CLASS_OPEN
+            | CLASS_CLOSE
+            | START_ANCHOR
+            | END_ANCHOR
+            | ESCAPE
+            | GROUP_KIND
+    )
+}
+
+// ? Recursive descent over the grammar, lowest precedence first:
+// ?   alternation := concat ('|' concat)*
+// ?   concat      := repetition+
+// ?   repetition  := atom quantifier*
+// ?   quantifier  := ('*' | '+' | '?' | '{' m '}' | '{' m ',}' | '{' m ',' n '}') '?'?
+// ?   atom        := '(' name? alternation ')' | '[' class ']' | '^' | '$' | '\A' | '\z'
+// ?                | '\b' | '\B' | '\' char | char
+// ?   name        := '?<' ident '>' | '?P<' ident '>'
+pub struct Parser<'a> {
+    pattern: &'a str,
+    pos: usize,
+    group_count: usize,
+    group_names: Vec<String>,
+    unicode: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(pattern: &'a str) -> Parser<'a> {
+        Parser {
+            pattern,
+            pos: 0,
+            group_count: 0,
+            group_names: Vec::new(),
+            unicode: true,
+        }
+    }
+
+    pub fn parse(pattern: &str) -> Result<Ast, RegexError> {
+        Parser::parse_with_unicode(pattern, true)
+    }
+
+    // ? with `unicode` off only ASCII letters, digits and '_' are word chars to
+    // ? `\b` and `\B`, any other alphanumeric char counts as a word char otherwise
+    pub fn parse_with_unicode(pattern: &str, unicode: bool) -> Result<Ast, RegexError> {
+        let mut parser = Parser::new(pattern);
+        parser.unicode = unicode;
+
+        if pattern.is_empty() {
+            return Ok(Ast::Empty);
+        }
+
+        let ast = parser.parse_alternation()?;
+
+        // ? parse_alternation only stops early on a ')' it has no group for
+        match parser.peek() {
+            None => Ok(ast),
+            Some(_) => Err(RegexError::UnmatchedGroupClose(parser.span_of_next())),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let symbol = self.peek()?;
+        self.pos += symbol.len_utf8();
+        Some(symbol)
+    }
+
+    fn span_of_next(&self) -> Span {
+        let len = self.peek().map_or(0, char::len_utf8);
+        Span::new(self.pos, self.pos + len)
+    }
+
+    fn span_of_prev(&self) -> Span {
+        let len = self.pattern[..self.pos]
+            .chars()
+            .next_back()
+            .map_or(0, char::len_utf8);
+        Span::new(self.pos - len, self.pos)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Ast, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+
+        while self.peek() == Some(ALTERNATION) {
+            self.next();
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(Ast::alternation(branches))
+    }
+
+    fn parse_concat(&mut self) -> Result<Ast, RegexError> {
+        let mut array_of_ast: Vec<Ast> = Vec::new();
+
+        while let Some(symbol) = self.peek() {
+            if symbol == ALTERNATION || symbol == GROUP_CLOSE {
+                break;
+            }
+            array_of_ast.push(self.parse_repetition()?);
+        }
+
+        // ? point at the '|' or ')' that closed the empty branch
+        if array_of_ast.is_empty() {
+            let span = match self.peek() {
+                Some(_) => self.span_of_next(),
+                None => self.span_of_prev(),
+            };
+            return Err(RegexError::EmptyExpression(span));
+        }
+        Ok(Ast::concat(array_of_ast))
+    }
+
+    fn parse_repetition(&mut self) -> Result<Ast, RegexError> {
+        let mut ast = self.parse_atom()?;
+
+        loop {
+            let bounds = match self.peek() {
+                Some(KLEENE_STAR) => Some((0, None)),
+                Some(ONE_OR_MORE) => Some((1, None)),
+                Some(ZERO_OR_ONE) => Some((0, Some(1))),
+                _ => None,
+            };
+            let (min, max) = match bounds {
+                Some(bounds) => {
+                    self.next();
+                    bounds
+                }
+                None if self.peek() == Some(REPEAT_OPEN) => self.parse_counted_repetition()?,
+                None => return Ok(ast),
+            };
+
+            // ? a '?' right after a quantifier makes it lazy
+            ast = match self.peek() {
+                Some(ZERO_OR_ONE) => {
+                    self.next();
+                    Ast::lazy_repeat(ast, min, max)
+                }
+                _ => Ast::repeat(ast, min, max),
+            };
+        }
+    }
+
+    // ? called on the '{' of a quantifier, errors point from the '{' to where
+    // ? parsing stopped
+    fn parse_counted_repetition(&mut self) -> Result<(u32, Option<u32>), RegexError> {
+        let start = self.pos;
+        self.next();
+
+        let min = self.parse_count(start)?;
+        let max = match self.next() {
+            Some(REPEAT_CLOSE) => return Ok((min, Some(min))),
+            Some(REPEAT_SEPARATOR) if self.peek() == Some(REPEAT_CLOSE) => {
+                self.next();
+                return Ok((min, None));
+            }
+            Some(REPEAT_SEPARATOR) => self.parse_count(start)?,
+            _ => return Err(RegexError::InvalidRepetition(Span::new(start, self.pos))),
+        };
+
+        if self.next() != Some(REPEAT_CLOSE) {
+            return Err(RegexError::InvalidRepetition(Span::new(start, self.pos)));
+        }
+        if max < min {
+            return Err(RegexError::InvalidRepetitionRange(Span::new(
+                start, self.pos,
+            )));
+        }
+        Ok((min, Some(max)))
+    }
+
+    fn parse_count(&mut self, start: usize) -> Result<u32, RegexError> {
+        let digits_start = self.pos;
+        while self.peek().is_some_and(|symbol| symbol.is_ascii_digit()) {
+            self.next();
+        }
+
+        self.pattern[digits_start..self.pos]
+            .parse()
+            .map_err(|_| RegexError::InvalidRepetition(Span::new(start, self.pos.max(start + 1))))
+    }
+
+    // ? parse_concat never calls this at the end of the pattern or on '|' and ')'
+    fn parse_atom(&mut self) -> Result<Ast, RegexError> {
+        let start = self.pos;
+
+        match self.next() {
+            Some(GROUP_OPEN) => {
+                self.group_count += 1;
+                let index = self.group_count;
+                let name = match self.peek() {
+                    Some(GROUP_KIND) => Some(self.parse_group_name(start)?),
+                    _ => None,
+                };
+                if self.peek().is_none() {
+                    return Err(RegexError::UnclosedGroup(Span::new(
+                        start,
+                        self.pattern.len(),
+                    )));
+                }
+                let ast = self.parse_alternation()?;
+                match self.next() {
+                    Some(GROUP_CLOSE) => Ok(Ast::Group {
+                        index,
+                        name,
+                        ast: Box::new(ast),
+                    }),
+                    _ => Err(RegexError::UnclosedGroup(Span::new(
+                        start,
+                        self.pattern.len(),
+                    ))),
+                }
+            }
+            Some(CLASS_OPEN) => self.parse_class(start),
+            Some(START_ANCHOR) => Ok(Ast::Anchor(Anchor::Start)),
+            Some(END_ANCHOR) => Ok(Ast::Anchor(Anchor::End)),
+            Some(KLEENE_STAR | ONE_OR_MORE | ZERO_OR_ONE | REPEAT_OPEN) => {
+                Err(RegexError::NothingToRepeat(self.span_of_prev()))
+            }
+            Some(ESCAPE) => match self.peek() {
+                Some('A') => {
+                    self.next();
+                    Ok(Ast::Anchor(Anchor::StartText))
+                }
+                Some('z') => {
+                    self.next();
+                    Ok(Ast::Anchor(Anchor::EndText))
+                }
+                Some('b') => {
+                    self.next();
+                    if self.unicode {
+                        Ok(Ast::Anchor(Anchor::WordBoundary))
+                    } else {
+                        Ok(Ast::Anchor(Anchor::AsciiWordBoundary))
+                    }
+                }
+                Some('B') => {
+                    self.next();
+                    if self.unicode {
+                        Ok(Ast::Anchor(Anchor::NotWordBoundary))
+                    } else {
+                        Ok(Ast::Anchor(Anchor::AsciiNotWordBoundary))
+                    }
+                }
+                _ => Ok(Ast::Literal(self.parse_escape(start)?)),
+            },
+            Some(symbol) => Ok(Ast::Literal(symbol)),
+            None => Err(RegexError::EmptyExpression(Span::new(start, start))),
+        }
+    }
+
+    // ? called on the '?' following the '(' at `start`, names are an ASCII letter or
+    // ? '_' followed by letters, digits or '_', and must be unique in the pattern
+    fn parse_group_name(&mut self, start: usize) -> Result<String, RegexError> {
+        self.next();
+        if self.peek() == Some('P') {
+            self.next();
+        }
+        if self.next() != Some(GROUP_NAME_OPEN) {
+            return Err(RegexError::UnknownGroupKind(Span::new(start, self.pos)));
+        }
+
+        let name_start = self.pos;
+        let name_end = match self.pattern[name_start..].find(GROUP_NAME_CLOSE) {
+            Some(len) => name_start + len,
+            None => {
+                return Err(RegexError::InvalidGroupName(Span::new(
+                    name_start,
+                    self.pattern.len(),
+                )))
+            }
+        };
+        self.pos = name_end + GROUP_NAME_CLOSE.len_utf8();
+
+        let name = &self.pattern[name_start..name_end];
+        let span = Span::new(name_start, name_end);
+        let is_valid = name
+            .starts_with(|symbol: char| symbol.is_ascii_alphabetic() || symbol == '_')
+            && name
+                .chars()
+                .all(|symbol| symbol.is_ascii_alphanumeric() || symbol == '_');
+
+        if !is_valid {
+            return Err(RegexError::InvalidGroupName(span));
+        }
+        if self.group_names.iter().any(|other| other == name) {
+            return Err(RegexError::DuplicateGroupName(span));
+        }
+
+        self.group_names.push(name.to_string());
+        Ok(name.to_string())
+    }
+
+    // ? called with the '\' already consumed, letters and digits are reserved
+    // ? for escape sequences so only the known ones are accepted
+    fn parse_escape(&mut self, start: usize) -> Result<char, RegexError> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some(symbol) if symbol.is_ascii_alphanumeric() => {
+                Err(RegexError::BadEscape(Span::new(start, self.pos)))
+            }
+            Some(symbol) => Ok(symbol),
+            None => Err(RegexError::DanglingEscape(Span::new(start, self.pos))),
+        }
+    }
+
+    // ? called with the opening '[' already consumed, a leading '^' negates the class
+    fn parse_class(&mut self, start: usize) -> Result<Ast, RegexError> {
+        let mut ranges: Vec<ClassRange> = Vec::new();
+        let negated = self.peek() == Some(CLASS_NEGATE);
+        if negated {
+            self.next();
+        }
+
+        loop {
+            let range_pos = self.pos;
+            let range_start = match self.next() {
+                Some(CLASS_CLOSE) if !ranges.is_empty() && negated => {
+                    return Ok(Ast::NegatedClass(ranges))
+                }
+                Some(CLASS_CLOSE) if !ranges.is_empty() => return Ok(Ast::Class(ranges)),
+                Some(CLASS_CLOSE) => {
+                    return Err(RegexError::EmptyClass(Span::new(start, self.pos)))
+                }
+                Some(ESCAPE) => self.parse_escape(self.pos - 1)?,
+                Some(symbol) => symbol,
+                None => {
+                    return Err(RegexError::UnclosedClass(Span::new(
+                        start,
+                        self.pattern.len(),
+                    )))
+                }
+            };
+
+            let is_range = self.peek() == Some(CLASS_RANGE)
+                && !self.pattern[self.pos + 1..].starts_with(CLASS_CLOSE);
+
+            if !is_range {
+                ranges.push(ClassRange::new(range_start, range_start));
+                continue;
+            }
+
+            self.next();
+            let range_end = match self.next() {
+                Some(ESCAPE) => self.parse_escape(self.pos - 1)?,
+                Some(symbol) => symbol,
+                None => {
+                    return Err(RegexError::UnclosedClass(Span::new(
+                        start,
+                        self.pattern.len(),
+                    )))
+                }
+            };
+
+            if range_end < range_start {
+                return Err(RegexError::InvalidClassRange(Span::new(
+                    range_pos, self.pos,
+                )));
+            }
+            ranges.push(ClassRange::new(range_start, range_end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn lit(symbol: char) -> Ast {
+        Ast::Literal(symbol)
+    }
+
+    #[test]
+    fn test_parse_char_and_concat() {
+        assert_eq!(Parser::parse("a").unwrap(), lit('a'));
+        assert_eq!(
+            Parser::parse("abc").unwrap(),
+            Ast::Concat(vec![lit('a'), lit('b'), lit('c')])
+        );
+        assert_eq!(Parser::parse("").unwrap(), Ast::Empty);
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        // ? star binds tighter than concat, concat tighter than alternation
+        assert_eq!(
+            Parser::parse("ab*|c").unwrap(),
+            Ast::Alternation(vec![
+                Ast::Concat(vec![lit('a'), Ast::repetition(lit('b'))]),
+                lit('c'),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_quantifiers() {
+        assert_eq!(
+            Parser::parse("a+b?c{2}d{2,}e{2,5}").unwrap(),
+            Ast::Concat(vec![
+                Ast::repeat(lit('a'), 1, None),
+                Ast::repeat(lit('b'), 0, Some(1)),
+                Ast::repeat(lit('c'), 2, Some(2)),
+                Ast::repeat(lit('d'), 2, None),
+                Ast::repeat(lit('e'), 2, Some(5)),
+            ])
+        );
+        // ? quantifiers stack, each one applies to everything before it
+        assert_eq!(
+            Parser::parse("a*+").unwrap(),
+            Ast::repeat(Ast::repetition(lit('a')), 1, None)
+        );
+        assert_eq!(
+            Parser::parse("(ab){0}").unwrap(),
+            Ast::repeat(Ast::group(1, Ast::literal_str("ab")), 0, Some(0))
+        );
+        assert_eq!(Parser::parse("a}").unwrap(), Ast::literal_str("a}"));
+    }
+
+    #[test]
+    fn test_parse_lazy_quantifiers() {
+        assert_eq!(
+            Parser::parse("a*?b+?c??d{2,3}?").unwrap(),
+            Ast::Concat(vec![
+                Ast::lazy_repeat(lit('a'), 0, None),
+                Ast::lazy_repeat(lit('b'), 1, None),
+                Ast::lazy_repeat(lit('c'), 0, Some(1)),
+                Ast::lazy_repeat(lit('d'), 2, Some(3)),
+            ])
+        );
+        // ? only one '?' belongs to the quantifier, a second one repeats again
+        assert_eq!(
+            Parser::parse("a*??").unwrap(),
+            Ast::repeat(Ast::lazy_repeat(lit('a'), 0, None), 0, Some(1))
+        );
+    }
+
+    #[test]
+    fn test_parse_group() {
+        assert_eq!(
+            Parser::parse("(a|b)*abb").unwrap(),
+            Ast::Concat(vec![
+                Ast::repetition(Ast::group(1, Ast::Alternation(vec![lit('a'), lit('b')]))),
+                lit('a'),
+                lit('b'),
+                lit('b'),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_group_numbering() {
+        // ? numbered by the position of their opening parenthesis
+        assert_eq!(
+            Parser::parse("((a)|(b))").unwrap(),
+            Ast::group(
+                1,
+                Ast::Alternation(vec![Ast::group(2, lit('a')), Ast::group(3, lit('b'))])
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_named_group() {
+        assert_eq!(
+            Parser::parse("(?P<year>a)(b)(?<day>c)").unwrap(),
+            Ast::Concat(vec![
+                Ast::named_group(1, "year", lit('a')),
+                Ast::group(2, lit('b')),
+                Ast::named_group(3, "day", lit('c')),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_escape() {
+        assert_eq!(
+            Parser::parse("a\\*\\(").unwrap(),
+            Ast::Concat(vec![lit('a'), lit('*'), lit('(')])
+        );
+    }
+
+    #[test]
+    fn test_parse_class() {
+        assert_eq!(
+            Parser::parse("[a-c_]").unwrap(),
+            Ast::Class(vec![ClassRange::new('a', 'c'), ClassRange::new('_', '_')])
+        );
+        assert_eq!(
+            Parser::parse("[a-]").unwrap(),
+            Ast::Class(vec![ClassRange::new('a', 'a'), ClassRange::new('-', '-')])
+        );
+        assert_eq!(
+            Parser::parse("[\\]]").unwrap(),
+            Ast::Class(vec![ClassRange::new(']', ']')])
+        );
+    }
+
+    #[test]
+    fn test_parse_negated_class() {
+        assert_eq!(
+            Parser::parse("[^a-c\n]").unwrap(),
+            Ast::NegatedClass(vec![ClassRange::new('a', 'c'), ClassRange::new('\n', '\n')])
+        );
+        assert_eq!(
+            Parser::parse("[\\^a]").unwrap(),
+            Ast::Class(vec![ClassRange::new('^', '^'), ClassRange::new('a', 'a')])
+        );
+        assert_eq!(
+            Parser::parse("[a^]").unwrap(),
+            Ast::Class(vec![ClassRange::new('a', 'a'), ClassRange::new('^', '^')])
+        );
+        assert_eq!(
+            Parser::parse("[^]"),
+            Err(RegexError::EmptyClass(Span::new(0, 3)))
+        );
+        assert_eq!(
+            Parser::parse("[^ab"),
+            Err(RegexError::UnclosedClass(Span::new(0, 4)))
+        );
+    }
+
+    #[test]
+    fn test_parse_anchor() {
+        assert_eq!(
+            Parser::parse("^a$").unwrap(),
+            Ast::Concat(vec![
+                Ast::Anchor(Anchor::Start),
+                lit('a'),
+                Ast::Anchor(Anchor::End),
+            ])
+        );
+        assert_eq!(
+            Parser::parse("\\Aa\\z").unwrap(),
+            Ast::Concat(vec![
+                Ast::Anchor(Anchor::StartText),
+                lit('a'),
+                Ast::Anchor(Anchor::EndText),
+            ])
+        );
+        assert_eq!(
+            Parser::parse("\\ba\\B").unwrap(),
+            Ast::Concat(vec![
+                Ast::Anchor(Anchor::WordBoundary),
+                lit('a'),
+                Ast::Anchor(Anchor::NotWordBoundary),
+            ])
+        );
+        assert_eq!(
+            Parser::parse_with_unicode("\\b|\\B", false).unwrap(),
+            Ast::Alternation(vec![
+                Ast::Anchor(Anchor::AsciiWordBoundary),
+                Ast::Anchor(Anchor::AsciiNotWordBoundary),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_escape_sequences() {
+        assert_eq!(Parser::parse("\\n").unwrap(), lit('\n'));
+        assert_eq!(Parser::parse("\\.").unwrap(), lit('.'));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("(ab", RegexError::UnclosedGroup(Span::new(0, 3))),
+            ("(", RegexError::UnclosedGroup(Span::new(0, 1))),
+            ("a(?<a>", RegexError::UnclosedGroup(Span::new(1, 6))),
+            ("ab)", RegexError::UnmatchedGroupClose(Span::new(2, 3))),
+            ("*a", RegexError::NothingToRepeat(Span::new(0, 1))),
+            ("+a", RegexError::NothingToRepeat(Span::new(0, 1))),
+            ("a|?", RegexError::NothingToRepeat(Span::new(2, 3))),
+            ("{2}", RegexError::NothingToRepeat(Span::new(0, 1))),
+            ("a{", RegexError::InvalidRepetition(Span::new(1, 2))),
+            ("a{x}", RegexError::InvalidRepetition(Span::new(1, 2))),
+            ("a{2", RegexError::InvalidRepetition(Span::new(1, 3))),
+            ("a{2,x}", RegexError::InvalidRepetition(Span::new(1, 4))),
+            ("a{2,3", RegexError::InvalidRepetition(Span::new(1, 5))),
+            (
+                "a{99999999999}",
+                RegexError::InvalidRepetition(Span::new(1, 13)),
+            ),
+            (
+                "a{3,2}b",
+                RegexError::InvalidRepetitionRange(Span::new(1, 6)),
+            ),
+            ("a|*", RegexError::NothingToRepeat(Span::new(2, 3))),
+            ("a|", RegexError::EmptyExpression(Span::new(1, 2))),
+            ("|a", RegexError::EmptyExpression(Span::new(0, 1))),
+            ("a()", RegexError::EmptyExpression(Span::new(2, 3))),
+            ("a\\", RegexError::DanglingEscape(Span::new(1, 2))),
+            ("a\\q", RegexError::BadEscape(Span::new(1, 3))),
+            ("[\\A]", RegexError::BadEscape(Span::new(1, 3))),
+            ("[a\\b]", RegexError::BadEscape(Span::new(2, 4))),
+            ("[]", RegexError::EmptyClass(Span::new(0, 2))),
+            ("a[z-a]", RegexError::InvalidClassRange(Span::new(2, 5))),
+            ("[ab", RegexError::UnclosedClass(Span::new(0, 3))),
+            ("(?x)", RegexError::UnknownGroupKind(Span::new(0, 3))),
+            ("(?<>a)", RegexError::InvalidGroupName(Span::new(3, 3))),
+            ("(?<1a>a)", RegexError::InvalidGroupName(Span::new(3, 5))),
+            ("(?<a", RegexError::InvalidGroupName(Span::new(3, 4))),
+            (
+                "(?<x>a)(?P<x>b)",
+                RegexError::DuplicateGroupName(Span::new(11, 12)),
+            ),
+        ];
+
+        for (pattern, error) in cases {
+            assert_eq!(Parser::parse(pattern), Err(error), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_parse_error_render() {
+        let pattern = "ab(c|d";
+        let error = Parser::parse(pattern).unwrap_err();
+
+        assert_eq!(
+            error.render(pattern),
+            "error: unclosed group, missing ')'\n  ab(c|d\n    ^~~~"
+        );
+    }
+}
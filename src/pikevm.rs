@@ -0,0 +1,572 @@
+use crate::captures::Captures;
+use crate::nfa::NFA;
+use crate::search::{Anchored, MatchKind};
+use crate::state::{State, StateId};
+
+// ? Insertion-ordered set of state ids with O(1) insert, lookup and clear.
+// ? `dense` keeps the order threads were added in, `sparse` points back into it.
+#[derive(Clone, Debug)]
+pub struct SparseSet {
+    dense: Vec<StateId>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    pub fn new(capacity: usize) -> SparseSet {
+        SparseSet {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    pub fn contains(&self, id: StateId) -> bool {
+        let index = self.sparse[id.index()];
+        index < self.dense.len() && self.dense[index] == id
+    }
+
+    pub fn insert(&mut self, id: StateId) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        self.sparse[id.index()] = self.dense.len();
+        self.dense.push(id);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StateId> {
+        self.dense.iter()
+    }
+}
+
+// ? Active threads for one input position. `slots[state]` holds the capture
+// ? positions of the thread sitting in that state, only valid while it is in `set`.
+#[derive(Clone, Debug)]
+struct Threads {
+    set: SparseSet,
+    slots: Vec<Vec<Option<usize>>>,
+}
+
+impl Threads {
+    fn new(state_count: usize, slot_count: usize) -> Threads {
+        Threads {
+            set: SparseSet::new(state_count),
+            slots: vec![vec![None; slot_count]; state_count],
+        }
+    }
+}
+
+// ? work items for the ε walk; a Restore undoes a capture once every state
+// ? reachable after it has been visited, like returning from a recursive call
+enum Frame {
+    Explore(StateId),
+    Restore(usize, Option<usize>),
+}
+
+// ? Thompson/Pike simulation: every NFA state is active at most once per input
+// ? position, so a run costs O(states * input) and never recurses on the input.
+pub struct PikeVM<'a> {
+    nfa: &'a NFA,
+}
+
+impl<'a> PikeVM<'a> {
+    pub fn new(nfa: &'a NFA) -> PikeVM<'a> {
+        PikeVM { nfa }
+    }
+
+    // ? whole-input match, the same question NFA::test answers
+    pub fn is_match(&self, input: &str) -> bool {
+        let state_count = self.nfa.states.len();
+        let mut curr_list = SparseSet::new(state_count);
+        let mut next_list = SparseSet::new(state_count);
+        let mut stack: Vec<StateId> = Vec::new();
+
+        self.add_state(&mut curr_list, &mut stack, input, 0, self.nfa.in_state);
+
+        for (pos, symbol) in input.char_indices() {
+            if curr_list.is_empty() {
+                return false;
+            }
+
+            let next_pos = pos + symbol.len_utf8();
+            for state_id in curr_list.iter() {
+                for next_state in self.nfa.state(*state_id).get_transitions_for_char(symbol) {
+                    self.add_state(&mut next_list, &mut stack, input, next_pos, *next_state);
+                }
+            }
+
+            std::mem::swap(&mut curr_list, &mut next_list);
+            next_list.clear();
+        }
+
+        let is_match = curr_list
+            .iter()
+            .any(|state_id| self.nfa.state(*state_id).accepting);
+        is_match
+    }
+
+    // ? Whole-input match that also reports capture positions. Threads are kept in
+    // ? priority order (earlier ε edges first), so alternation prefers its left branch
+    // ? and the star prefers another iteration; the first thread accepting at the end wins.
+    pub fn captures<'h>(&self, input: &'h str) -> Option<Captures<'h>> {
+        let state_count = self.nfa.states.len();
+        let slot_count = self.nfa.group_count() * 2;
+
+        let mut curr_threads = Threads::new(state_count, slot_count);
+        let mut next_threads = Threads::new(state_count, slot_count);
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut slots: Vec<Option<usize>> = vec![None; slot_count];
+
+        self.add_thread(
+            &mut curr_threads,
+            &mut stack,
+            &mut slots,
+            input,
+            self.nfa.in_state,
+            0,
+        );
+
+        for (pos, symbol) in input.char_indices() {
+            if curr_threads.set.is_empty() {
+                return None;
+            }
+
+            let next_pos = pos + symbol.len_utf8();
+            for state_id in curr_threads.set.iter() {
+                for next_state in self.nfa.state(*state_id).get_transitions_for_char(symbol) {
+                    slots.clone_from(&curr_threads.slots[state_id.index()]);
+                    self.add_thread(
+                        &mut next_threads,
+                        &mut stack,
+                        &mut slots,
+                        input,
+                        *next_state,
+                        next_pos,
+                    );
+                }
+            }
+
+            std::mem::swap(&mut curr_threads, &mut next_threads);
+            next_threads.set.clear();
+        }
+
+        let accepting_state = curr_threads
+            .set
+            .iter()
+            .find(|state_id| self.nfa.state(**state_id).accepting)?;
+        Some(Captures::new(
+            input,
+            curr_threads.slots[accepting_state.index()].clone(),
+            self.nfa.group_names.clone(),
+        ))
+    }
+
+    // ? Search from `start`. With Anchored::No a fresh thread is seeded at every
+    // ? position behind the existing ones until something matches, which is the
+    // ? `.*?` prefix without putting it in the NFA, so threads stay sorted by start.
+    // ? LeftmostFirst: once a thread accepts, the threads after it have lower
+    // ? priority and are dropped, the rest only run on to see if they finish.
+    // ? LeftmostLongest: every thread runs on, and a later accepting thread wins
+    // ? unless it started further right. Match starts come from group 0, so the
+    // ? NFA is expected to come from `Compiler::compile`.
+    pub fn search<'h>(
+        &self,
+        input: &'h str,
+        start: usize,
+        anchored: Anchored,
+        match_kind: MatchKind,
+    ) -> Option<Captures<'h>> {
+        let state_count = self.nfa.states.len();
+        let slot_count = self.nfa.group_count() * 2;
+
+        let mut curr_threads = Threads::new(state_count, slot_count);
+        let mut next_threads = Threads::new(state_count, slot_count);
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut slots: Vec<Option<usize>> = vec![None; slot_count];
+        let mut matched: Option<Vec<Option<usize>>> = None;
+
+        let mut pos = start;
+        loop {
+            if matched.is_none() && (pos == start || anchored == Anchored::No) {
+                slots.fill(None);
+                self.add_thread(
+                    &mut curr_threads,
+                    &mut stack,
+                    &mut slots,
+                    input,
+                    self.nfa.in_state,
+                    pos,
+                );
+            }
+            if curr_threads.set.is_empty() && (matched.is_some() || anchored == Anchored::Yes) {
+                break;
+            }
+
+            let symbol = input[pos..].chars().next();
+            let mut matched_here = false;
+            for state_id in curr_threads.set.iter() {
+                let thread_slots = &curr_threads.slots[state_id.index()];
+                if self.nfa.state(*state_id).accepting {
+                    match match_kind {
+                        MatchKind::LeftmostFirst => {
+                            matched = Some(thread_slots.clone());
+                            break;
+                        }
+                        MatchKind::LeftmostLongest => {
+                            let is_leftmost = matched
+                                .as_ref()
+                                .is_none_or(|best| thread_slots.first() <= best.first());
+                            if !matched_here && is_leftmost {
+                                matched = Some(thread_slots.clone());
+                            }
+                            matched_here = true;
+                            continue;
+                        }
+                    }
+                }
+
+                let symbol = match symbol {
+                    Some(symbol) => symbol,
+                    None => continue,
+                };
+                let next_pos = pos + symbol.len_utf8();
+                for next_state in self.nfa.state(*state_id).get_transitions_for_char(symbol) {
+                    slots.clone_from(thread_slots);
+                    self.add_thread(
+                        &mut next_threads,
+                        &mut stack,
+                        &mut slots,
+                        input,
+                        *next_state,
+                        next_pos,
+                    );
+                }
+            }
+
+            match symbol {
+                Some(symbol) => pos += symbol.len_utf8(),
+                None => break,
+            }
+            std::mem::swap(&mut curr_threads, &mut next_threads);
+            next_threads.set.clear();
+        }
+
+        Some(Captures::new(input, matched?, self.nfa.group_names.clone()))
+    }
+
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        stack: &mut Vec<Frame>,
+        slots: &mut [Option<usize>],
+        input: &str,
+        state_id: StateId,
+        pos: usize,
+    ) {
+        stack.push(Frame::Explore(state_id));
+
+        while let Some(frame) = stack.pop() {
+            let curr_id = match frame {
+                Frame::Explore(curr_id) => curr_id,
+                Frame::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            if !threads.set.insert(curr_id) {
+                continue;
+            }
+
+            let state = self.nfa.state(curr_id);
+            if let Some(slot) = state.capture {
+                stack.push(Frame::Restore(slot, slots[slot]));
+                slots[slot] = Some(pos);
+            }
+            threads.slots[curr_id.index()].copy_from_slice(slots);
+
+            let next_states = PikeVM::get_epsilon_targets(state, input, pos).rev();
+            stack.extend(next_states.map(Frame::Explore));
+        }
+    }
+
+    // ? adds a state and everything reachable from it through ε, with an explicit
+    // ? stack so long ε chains can't overflow; targets are pushed in reverse so they
+    // ? are visited in the order they were added to the state
+    fn add_state(
+        &self,
+        list: &mut SparseSet,
+        stack: &mut Vec<StateId>,
+        input: &str,
+        pos: usize,
+        state_id: StateId,
+    ) {
+        stack.push(state_id);
+
+        while let Some(curr_id) = stack.pop() {
+            if !list.insert(curr_id) {
+                continue;
+            }
+            let state = self.nfa.state(curr_id);
+            stack.extend(PikeVM::get_epsilon_targets(state, input, pos).rev());
+        }
+    }
+
+    // ? the ε moves, then the moves of every assertion that holds at `pos`
+    fn get_epsilon_targets<'s>(
+        state: &'s State,
+        input: &'s str,
+        pos: usize,
+    ) -> impl DoubleEndedIterator<Item = StateId> + 's {
+        let looks = state
+            .look_transitions
+            .iter()
+            .filter(move |(look, _)| look.is_match_at(input, pos))
+            .flat_map(|(_, next_states)| next_states.iter());
+        state.epsilon_transitions.iter().chain(looks).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{compiler::Compiler, parser::Parser};
+
+    fn compile(pattern: &str) -> NFA {
+        Compiler::compile(&Parser::parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn test_sparse_set() {
+        let mut set = SparseSet::new(4);
+
+        assert!(set.insert(StateId(2)));
+        assert!(set.insert(StateId(0)));
+        assert!(!set.insert(StateId(2)));
+        assert!(set.contains(StateId(0)));
+        assert!(!set.contains(StateId(1)));
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![StateId(2), StateId(0)]
+        );
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(StateId(2)));
+    }
+
+    #[test]
+    fn test_is_match() {
+        let nfa = compile("(a|b)*abb");
+        let vm = PikeVM::new(&nfa);
+
+        assert!(vm.is_match("abb"));
+        assert!(vm.is_match("babaabb"));
+        assert!(!vm.is_match(""));
+        assert!(!vm.is_match("abba"));
+        assert!(!vm.is_match("abbc"));
+    }
+
+    #[test]
+    fn test_is_match_nested_star_is_linear() {
+        // ? exponential for a backtracking search, the subject doesn't end in 'b'
+        let nfa = compile("(a*)*b");
+        let input = "a".repeat(5_000);
+
+        assert!(!PikeVM::new(&nfa).is_match(&input));
+        assert!(PikeVM::new(&nfa).is_match(&format!("{}b", input)));
+    }
+
+    #[test]
+    fn test_is_match_long_input() {
+        let nfa = compile("(ab)*");
+        let input = "ab".repeat(100_000);
+
+        assert!(nfa.test(&input));
+        assert!(!nfa.test(&format!("{}a", input)));
+    }
+
+    fn get_groups(pattern: &str, input: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        let nfa = compile(pattern);
+        let caps = PikeVM::new(&nfa).captures(input)?;
+        Some(
+            caps.iter()
+                .map(|group| group.map(|group| (group.start(), group.end())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_captures() {
+        assert_eq!(
+            get_groups("(a)(b)", "ab"),
+            Some(vec![Some((0, 2)), Some((0, 1)), Some((1, 2))])
+        );
+        assert_eq!(get_groups("(a)(b)", "abc"), None);
+        // ? the star keeps the last iteration
+        assert_eq!(
+            get_groups("x(a|b)*y", "xabay"),
+            Some(vec![Some((0, 5)), Some((3, 4))])
+        );
+    }
+
+    #[test]
+    fn test_captures_reports_branch() {
+        // ? only the branch that matched records positions
+        assert_eq!(
+            get_groups("(aa*)|(b)", "b"),
+            Some(vec![Some((0, 1)), None, Some((0, 1))])
+        );
+        assert_eq!(
+            get_groups("(aa*)|(b)", "aa"),
+            Some(vec![Some((0, 2)), Some((0, 2)), None])
+        );
+    }
+
+    #[test]
+    fn test_captures_priority() {
+        // ? both branches can match, the left one wins
+        assert_eq!(
+            get_groups("(a|ab)(c|bcd)", "abcd"),
+            Some(vec![Some((0, 4)), Some((0, 1)), Some((1, 4))])
+        );
+        // ? the greedy star takes as much as it can and leaves the rest
+        assert_eq!(
+            get_groups("(a*)(a*)", "aaa"),
+            Some(vec![Some((0, 3)), Some((0, 3)), Some((3, 3))])
+        );
+        // ? the lazy ones take as little as the rest of the pattern allows
+        assert_eq!(
+            get_groups("(a*?)(a*)", "aaa"),
+            Some(vec![Some((0, 3)), Some((0, 0)), Some((0, 3))])
+        );
+        assert_eq!(
+            get_groups("(a{1,3}?)(a+?)", "aaa"),
+            Some(vec![Some((0, 3)), Some((0, 1)), Some((1, 3))])
+        );
+    }
+
+    #[test]
+    fn test_captures_unicode_offsets() {
+        assert_eq!(
+            get_groups("é(ü)", "éü"),
+            Some(vec![Some((0, 4)), Some((2, 4))])
+        );
+    }
+
+    #[test]
+    fn test_captures_name() {
+        let nfa = compile("(?<year>[0-9][0-9])-(?P<month>[0-9][0-9])");
+        let caps = PikeVM::new(&nfa).captures("24-06").unwrap();
+
+        assert_eq!(caps.name("year").map(|group| group.as_str()), Some("24"));
+        assert_eq!(caps.name("month").map(|group| group.range()), Some(3..5));
+        assert_eq!(caps.name("day"), None);
+    }
+
+    fn get_search(pattern: &str, input: &str, anchored: Anchored) -> Option<(usize, usize)> {
+        let nfa = compile(pattern);
+        let caps = PikeVM::new(&nfa).search(input, 0, anchored, MatchKind::LeftmostFirst)?;
+        caps.get(0).map(|group| (group.start(), group.end()))
+    }
+
+    #[test]
+    fn test_search_unanchored() {
+        assert_eq!(get_search("ab*", "xxabbby", Anchored::No), Some((2, 6)));
+        assert_eq!(get_search("aab", "aaab", Anchored::No), Some((1, 4)));
+        assert_eq!(get_search("b*", "aab", Anchored::No), Some((0, 0)));
+        assert_eq!(get_search("ab", "xyz", Anchored::No), None);
+        // ? leftmost wins over longest, then alternation order decides
+        assert_eq!(get_search("bc|abcd", "abcd", Anchored::No), Some((0, 4)));
+        assert_eq!(get_search("a|ab", "xab", Anchored::No), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_search_anchored() {
+        assert_eq!(get_search("ab*", "abbx", Anchored::Yes), Some((0, 3)));
+        assert_eq!(get_search("ab*", "xab", Anchored::Yes), None);
+
+        let nfa = compile("b");
+        let caps = PikeVM::new(&nfa)
+            .search("abb", 1, Anchored::Yes, MatchKind::LeftmostFirst)
+            .unwrap();
+        assert_eq!(caps.get(0).map(|group| group.range()), Some(1..2));
+    }
+
+    #[test]
+    fn test_search_assertions() {
+        assert_eq!(get_search("^ab", "abab", Anchored::No), Some((0, 2)));
+        assert_eq!(get_search("^b", "abab", Anchored::No), None);
+        assert_eq!(get_search("ab$", "abab", Anchored::No), Some((2, 4)));
+        assert_eq!(get_search("a*$", "aba", Anchored::No), Some((2, 3)));
+        assert_eq!(get_search("\\A|b\\z", "ab", Anchored::No), Some((0, 0)));
+
+        // ? a search starting inside the haystack still sees the char before it
+        let nfa = compile("^b");
+        let vm = PikeVM::new(&nfa);
+        assert!(vm
+            .search("ab", 1, Anchored::Yes, MatchKind::LeftmostFirst)
+            .is_none());
+    }
+
+    #[test]
+    fn test_search_captures() {
+        let nfa = compile("(?<key>[a-z]*)=([0-9]*)");
+        let caps = PikeVM::new(&nfa)
+            .search("-- width=80;", 0, Anchored::No, MatchKind::LeftmostFirst)
+            .unwrap();
+
+        assert_eq!(&caps[0], "width=80");
+        assert_eq!(caps.name("key").map(|group| group.as_str()), Some("width"));
+        assert_eq!(&caps[2], "80");
+    }
+
+    #[test]
+    fn test_search_match_kind() {
+        let nfa = compile("(a|ab)(c|bcd)|abcde");
+        let vm = PikeVM::new(&nfa);
+        let get_groups = |match_kind| {
+            let caps = vm.search("xabcdef", 0, Anchored::No, match_kind).unwrap();
+            caps.iter()
+                .map(|group| group.map(|group| group.range()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            get_groups(MatchKind::LeftmostFirst),
+            vec![Some(1..5), Some(1..2), Some(2..5)]
+        );
+        assert_eq!(
+            get_groups(MatchKind::LeftmostLongest),
+            vec![Some(1..6), None, None]
+        );
+    }
+
+    #[test]
+    fn test_search_leftmost_longest_prefers_leftmost() {
+        // ? "bcdef" is longer but "ab" starts first
+        let nfa = compile("ab|bcdef");
+        let caps = PikeVM::new(&nfa)
+            .search("abcdef", 0, Anchored::No, MatchKind::LeftmostLongest)
+            .unwrap();
+
+        assert_eq!(caps.get(0).map(|group| group.range()), Some(0..2));
+    }
+
+    #[test]
+    fn test_captures_agrees_with_is_match() {
+        for (pattern, input) in [("(a*)*b", "aaab"), ("(a|b)*abb", "babb"), ("a(b)*", "ac")] {
+            let nfa = compile(pattern);
+            let vm = PikeVM::new(&nfa);
+
+            assert_eq!(vm.captures(input).is_some(), vm.is_match(input));
+        }
+    }
+}
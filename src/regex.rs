@@ -0,0 +1,943 @@
+use crate::captures::{Captures, Match};
+use crate::compiler::Compiler;
+use crate::dfa::DFA;
+use crate::error::{RegexError, Span};
+use crate::nfa::NFA;
+use crate::parser::Parser;
+use crate::pikevm::PikeVM;
+use crate::replace::Replacer;
+use crate::search::{Anchored, MatchKind};
+use std::borrow::Cow;
+
+// ? A compiled pattern: parsed, lowered to an NFA and determinized once when built.
+// ? A search reads each char a bounded number of times on the DFAs, or on the
+// ? Pike VM when they would go over `dfa_size_limit`.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    pattern: String,
+    anchored: Anchored,
+    match_kind: MatchKind,
+    nfa: NFA,
+    dfas: Option<SearchDfas>,
+}
+
+// ? `dfa` is anchored and gives the end of the match from a known start.
+// ? `unanchored_dfa` runs forward to where the leftmost-first match ends and
+// ? `reverse_dfa` runs back from there to where it starts.
+#[derive(Clone, Debug)]
+struct SearchDfas {
+    dfa: DFA,
+    unanchored_dfa: DFA,
+    reverse_dfa: DFA,
+}
+
+// ? most NFA states a pattern may compile to unless the builder says otherwise
+pub const DEFAULT_SIZE_LIMIT: usize = 10_000;
+
+// ? most cells (states times alphabet pieces) each DFA's table may have before
+// ? searches use the Pike VM instead
+pub const DEFAULT_DFA_SIZE_LIMIT: usize = 50_000;
+
+// ? Options for compiling a Regex, `Regex::new` uses the defaults.
+#[derive(Clone, Debug)]
+pub struct RegexBuilder {
+    pattern: String,
+    anchored: Anchored,
+    match_kind: MatchKind,
+    size_limit: usize,
+    dfa_size_limit: usize,
+    unicode: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            anchored: Anchored::No,
+            match_kind: MatchKind::LeftmostFirst,
+            size_limit: DEFAULT_SIZE_LIMIT,
+            dfa_size_limit: DEFAULT_DFA_SIZE_LIMIT,
+            unicode: true,
+        }
+    }
+
+    // ? Anchored::Yes only matches at the start of the haystack, and find_iter
+    // ? only continues while every match starts where the previous one ended
+    pub fn anchored(&mut self, anchored: Anchored) -> &mut RegexBuilder {
+        self.anchored = anchored;
+        self
+    }
+
+    pub fn match_kind(&mut self, match_kind: MatchKind) -> &mut RegexBuilder {
+        self.match_kind = match_kind;
+        self
+    }
+
+    // ? counted repetitions copy their operand, so `a{1000}{1000}` is a million
+    // ? states. Patterns over the limit fail to build with SizeLimitExceeded.
+    pub fn size_limit(&mut self, size_limit: usize) -> &mut RegexBuilder {
+        self.size_limit = size_limit;
+        self
+    }
+
+    // ? A few NFA states can need exponentially many DFA states, `(a|b)*a(a|b){20}`
+    // ? has to remember the last 21 chars. Over the limit the pattern still builds
+    // ? and searches run on the Pike VM, slower but still linear in the haystack.
+    // ? The limit counts table cells, states times alphabet pieces, since `\b`
+    // ? cuts the alphabet into a piece per run of Unicode word chars.
+    pub fn dfa_size_limit(&mut self, dfa_size_limit: usize) -> &mut RegexBuilder {
+        self.dfa_size_limit = dfa_size_limit;
+        self
+    }
+
+    // ? with Unicode off `\b` and `\B` only count `[0-9A-Za-z_]` as word chars
+    pub fn unicode(&mut self, unicode: bool) -> &mut RegexBuilder {
+        self.unicode = unicode;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, RegexError> {
+        let ast = Parser::parse_with_unicode(&self.pattern, self.unicode)?;
+        let nfa = Compiler::compile_with_limit(&ast, self.size_limit).ok_or(
+            RegexError::SizeLimitExceeded(Span::new(0, self.pattern.len())),
+        )?;
+        let dfas = self.build_dfas(&nfa);
+
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            anchored: self.anchored,
+            match_kind: self.match_kind,
+            nfa,
+            dfas,
+        })
+    }
+
+    // ? None as soon as one of the DFAs goes over the limit, a search needs all three
+    fn build_dfas(&self, nfa: &NFA) -> Option<SearchDfas> {
+        let limit = self.dfa_size_limit;
+        let dfa = match self.match_kind {
+            MatchKind::LeftmostFirst => {
+                DFA::from_nfa_leftmost_first_with_limit(nfa, Anchored::Yes, limit)
+            }
+            MatchKind::LeftmostLongest => DFA::from_nfa_with_limit(nfa, limit),
+        }?
+        .minimize_hopcroft();
+        let unanchored_dfa =
+            DFA::from_nfa_leftmost_first_with_limit(nfa, Anchored::No, limit)?.minimize_hopcroft();
+        let reverse_dfa = DFA::from_nfa_with_limit(&nfa.reverse(), limit)?.minimize_hopcroft();
+
+        Some(SearchDfas {
+            dfa,
+            unanchored_dfa,
+            reverse_dfa,
+        })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn nfa(&self) -> &NFA {
+        &self.nfa
+    }
+
+    // ? the anchored DFA, None when the pattern went over the DFA size limit
+    pub fn dfa(&self) -> Option<&DFA> {
+        self.dfas.as_ref().map(|dfas| &dfas.dfa)
+    }
+
+    pub fn anchored(&self) -> Anchored {
+        self.anchored
+    }
+
+    pub fn match_kind(&self) -> MatchKind {
+        self.match_kind
+    }
+
+    // ? true if the pattern matches anywhere in the haystack (or at its start when
+    // ? anchored), stops reading as soon as the answer is known
+    pub fn is_match(&self, haystack: &str) -> bool {
+        let Some(dfas) = &self.dfas else {
+            return self.find(haystack).is_some();
+        };
+        match self.anchored {
+            Anchored::Yes => dfas.dfa.longest_match_at(haystack, 0).is_some(),
+            Anchored::No => dfas
+                .unanchored_dfa
+                .earliest_match_end(haystack, 0)
+                .is_some(),
+        }
+    }
+
+    // ? the leftmost match, ties between matches starting there are settled by the
+    // ? MatchKind
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> FindMatches<'r, 'h> {
+        FindMatches {
+            regex: self,
+            haystack,
+            pos: 0,
+            last_end: None,
+        }
+    }
+
+    // ? The pieces of the haystack between matches. Matches at either end give an
+    // ? empty first or last piece, so ",a," splits into "", "a" and "".
+    pub fn split<'r, 'h>(&'r self, haystack: &'h str) -> Split<'r, 'h> {
+        Split {
+            haystack,
+            matches: self.find_iter(haystack),
+            last_end: Some(0),
+        }
+    }
+
+    // ? at most `limit` pieces, the last one is the rest of the haystack unsplit
+    pub fn splitn<'r, 'h>(&'r self, haystack: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            split: self.split(haystack),
+            limit,
+        }
+    }
+
+    // ? the DFAs find the match, the Pike VM only runs over the match itself
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        let found = self.find(haystack)?;
+        self.captures_at(haystack, found.start())
+    }
+
+    fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        PikeVM::new(&self.nfa).search(haystack, start, Anchored::Yes, self.match_kind)
+    }
+
+    // ? the groups of a match the DFAs found, just the whole match should the
+    // ? Pike VM not find it again
+    fn captures_of<'h>(&self, haystack: &'h str, found: Match<'h>) -> Captures<'h> {
+        self.captures_at(haystack, found.start())
+            .unwrap_or_else(|| {
+                let mut slots = vec![None; 2 * self.nfa.group_count()];
+                slots[0] = Some(found.start());
+                slots[1] = Some(found.end());
+                Captures::new(haystack, slots, self.nfa.group_names.clone())
+            })
+    }
+
+    // ? replaces the first match, see `Captures::expand` for the template syntax
+    // ? and `Replacer` for passing a closure instead
+    pub fn replace<R: Replacer>(&self, haystack: &str, replacer: R) -> String {
+        self.replacen(haystack, 1, replacer)
+    }
+
+    pub fn replace_all<R: Replacer>(&self, haystack: &str, replacer: R) -> String {
+        self.replacen(haystack, 0, replacer)
+    }
+
+    // ? replaces the first `limit` matches, 0 means all of them. The Pike VM only
+    // ? runs when the replacement needs the groups.
+    pub fn replacen<R: Replacer>(&self, haystack: &str, limit: usize, mut replacer: R) -> String {
+        let mut result = String::with_capacity(haystack.len());
+        let mut last_end = 0;
+        let literal = replacer.no_expansion().map(Cow::into_owned);
+
+        for (index, found) in self.find_iter(haystack).enumerate() {
+            if limit > 0 && index == limit {
+                break;
+            }
+            result.push_str(&haystack[last_end..found.start()]);
+            match &literal {
+                Some(literal) => result.push_str(literal),
+                None => replacer.replace_append(&self.captures_of(haystack, found), &mut result),
+            }
+            last_end = found.end();
+        }
+
+        result.push_str(&haystack[last_end..]);
+        result
+    }
+
+    // ? `start` must be on a char boundary. Unanchored, the forward scan finds the
+    // ? end of the leftmost-first match, and since no match starts further left
+    // ? the longest backward scan from that end lands on its start. Leftmost-longest
+    // ? shares the start and only needs one more forward scan for the end.
+    fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        let Some(dfas) = &self.dfas else {
+            return PikeVM::new(&self.nfa)
+                .search(haystack, start, self.anchored, self.match_kind)?
+                .get(0);
+        };
+        if self.anchored == Anchored::Yes {
+            let match_end = dfas.dfa.longest_match_at(haystack, start)?;
+            return Some(Match::new(haystack, start, match_end));
+        }
+
+        let first_end = dfas.unanchored_dfa.longest_match_at(haystack, start)?;
+        let match_start = dfas
+            .reverse_dfa
+            .longest_match_rev(haystack, start, first_end)
+            .expect("reverse scan misses a match the forward scan found");
+
+        let match_end = match self.match_kind {
+            MatchKind::LeftmostFirst => first_end,
+            MatchKind::LeftmostLongest => dfas.dfa.longest_match_at(haystack, match_start)?,
+        };
+        Some(Match::new(haystack, match_start, match_end))
+    }
+}
+
+// ? Successive non-overlapping matches. An empty match right where the previous
+// ? match ended is skipped, so "a*" on "ab" yields "a" and then "" at 2.
+// ? Anchored iteration ends after an empty match, as the next one can't touch it.
+pub struct FindMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl<'r, 'h> Iterator for FindMatches<'r, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        loop {
+            if self.pos > self.haystack.len() {
+                return None;
+            }
+
+            let found = self.regex.find_at(self.haystack, self.pos)?;
+            if found.is_empty() && self.regex.anchored == Anchored::Yes {
+                // ? the next search would have to skip a char, so no later match
+                // ? could start where this one ended
+                self.pos = self.haystack.len() + 1;
+                if self.last_end == Some(found.end()) {
+                    return None;
+                }
+            } else if found.is_empty() {
+                // ? step over the next char so the search always makes progress
+                self.pos = found.end()
+                    + self.haystack[found.end()..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+                if self.last_end == Some(found.end()) {
+                    continue;
+                }
+            } else {
+                self.pos = found.end();
+            }
+
+            self.last_end = Some(found.end());
+            return Some(found);
+        }
+    }
+}
+
+// ? `last_end` is where the next piece starts, None once the last one was returned
+pub struct Split<'r, 'h> {
+    haystack: &'h str,
+    matches: FindMatches<'r, 'h>,
+    last_end: Option<usize>,
+}
+
+impl<'r, 'h> Iterator for Split<'r, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        let last_end = self.last_end?;
+
+        match self.matches.next() {
+            Some(found) => {
+                self.last_end = Some(found.end());
+                Some(&self.haystack[last_end..found.start()])
+            }
+            None => {
+                self.last_end = None;
+                Some(&self.haystack[last_end..])
+            }
+        }
+    }
+}
+
+pub struct SplitN<'r, 'h> {
+    split: Split<'r, 'h>,
+    limit: usize,
+}
+
+impl<'r, 'h> Iterator for SplitN<'r, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                let last_end = self.split.last_end.take()?;
+                Some(&self.split.haystack[last_end..])
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn get_matches(pattern: &str, haystack: &str) -> Vec<(usize, usize)> {
+        Regex::new(pattern)
+            .unwrap()
+            .find_iter(haystack)
+            .map(|found| (found.start(), found.end()))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_reports_parse_errors() {
+        assert!(Regex::new("(a|b)*abb").is_ok());
+        assert!(matches!(
+            Regex::new("a(b"),
+            Err(RegexError::UnclosedGroup(_))
+        ));
+        assert_eq!(Regex::new("a|b").unwrap().as_str(), "a|b");
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert_eq!(
+            Regex::new("(a{100}){1000}").map(|_| ()),
+            Err(RegexError::SizeLimitExceeded(Span::new(0, 14)))
+        );
+        assert!(RegexBuilder::new("[a-z]{3}").size_limit(5).build().is_err());
+        assert!(RegexBuilder::new("[a-z]{3}").size_limit(20).build().is_ok());
+    }
+
+    #[test]
+    fn test_dfa_size_limit() {
+        // ? the DFA would need 2^21 states, the Pike VM runs instead
+        let regex = Regex::new("(a|b)*a(a|b){20}").unwrap();
+        assert!(regex.dfa().is_none());
+        assert!(regex.is_match("xbaaaaaaaaaaaaaaaaaaaaby"));
+        assert!(!regex.is_match("baaaaaaaaaaaaaaaaaaaa"));
+        assert_eq!(
+            regex
+                .find("xbabbbbbbbbbbbbbbbbbbbbby")
+                .map(|found| found.range()),
+            Some(1..23)
+        );
+
+        for anchored in [Anchored::Yes, Anchored::No] {
+            for match_kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+                let regex = RegexBuilder::new("a+|(b|a)(a|b)")
+                    .anchored(anchored)
+                    .match_kind(match_kind)
+                    .dfa_size_limit(4)
+                    .build()
+                    .unwrap();
+                let expected = RegexBuilder::new("a+|(b|a)(a|b)")
+                    .anchored(anchored)
+                    .match_kind(match_kind)
+                    .build()
+                    .unwrap();
+                assert!(regex.dfa().is_none() && expected.dfa().is_some());
+
+                for haystack in ["", "aab", "ba", "cbab", "ab"] {
+                    assert_eq!(regex.is_match(haystack), expected.is_match(haystack));
+                    assert_eq!(
+                        regex
+                            .find_iter(haystack)
+                            .map(|found| found.range())
+                            .collect::<Vec<_>>(),
+                        expected
+                            .find_iter(haystack)
+                            .map(|found| found.range())
+                            .collect::<Vec<_>>(),
+                        "{:?} {:?} on {:?}",
+                        anchored,
+                        match_kind,
+                        haystack
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_quantifiers() {
+        assert_eq!(
+            get_matches("[0-9]+", "a1b22c333"),
+            vec![(1, 2), (3, 5), (6, 9)]
+        );
+        assert_eq!(
+            get_matches("colou?r", "color colour"),
+            vec![(0, 5), (6, 12)]
+        );
+        assert_eq!(
+            get_matches("[0-9]{2,3}", "1 12 1234 12345"),
+            vec![(2, 4), (5, 8), (10, 13), (13, 15)]
+        );
+        assert_eq!(get_matches("a{2,}", "a aa aaaa"), vec![(2, 4), (5, 9)]);
+    }
+
+    #[test]
+    fn test_is_match() {
+        let regex = Regex::new("(a|b)*abb").unwrap();
+
+        assert!(regex.is_match("abb"));
+        assert!(regex.is_match("xxbabbxx"));
+        assert!(!regex.is_match("abab"));
+        assert!(!regex.is_match(""));
+    }
+
+    #[test]
+    fn test_find() {
+        let regex = Regex::new("ab*").unwrap();
+        let found = regex.find("xxabbbyab").unwrap();
+
+        assert_eq!(found.range(), 2..6);
+        assert_eq!(found.as_str(), "abbb");
+        assert_eq!(regex.find("xyz"), None);
+    }
+
+    #[test]
+    fn test_find_unicode_offsets() {
+        let regex = Regex::new("üb*").unwrap();
+
+        assert_eq!(regex.find("éüb").map(|found| found.range()), Some(2..5));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        assert_eq!(get_matches("ab", "abxabab"), vec![(0, 2), (3, 5), (5, 7)]);
+        assert_eq!(
+            get_matches("[0-9][0-9]*", "a1b22c333"),
+            vec![(1, 2), (3, 5), (6, 9)]
+        );
+        assert_eq!(get_matches("z", "abc"), vec![]);
+    }
+
+    #[test]
+    fn test_find_iter_negated_class() {
+        assert_eq!(
+            get_matches("[^ ,][^ ,]*", "ab, ü c,,d"),
+            vec![(0, 2), (4, 6), (7, 8), (10, 11)]
+        );
+        assert_eq!(get_matches("[^a-z]", "ab"), vec![]);
+    }
+
+    #[test]
+    fn test_is_match_reads_past_unknown_chars() {
+        let regex = Regex::new("aab").unwrap();
+
+        assert!(regex.is_match("xx aaab yy"));
+        assert!(!regex.is_match("xx aa ab yy"));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let regex = RegexBuilder::new("ab*")
+            .anchored(Anchored::Yes)
+            .build()
+            .unwrap();
+
+        assert_eq!(regex.anchored(), Anchored::Yes);
+        assert!(regex.is_match("abbx"));
+        assert!(!regex.is_match("xab"));
+        assert_eq!(regex.find("abbx").map(|found| found.range()), Some(0..3));
+        assert_eq!(regex.find("xab"), None);
+
+        // ? iteration stops at the first gap between matches
+        let matches: Vec<_> = regex
+            .find_iter("abaabxab")
+            .map(|found| found.range())
+            .collect();
+        assert_eq!(matches, vec![0..2, 2..3, 3..5]);
+
+        // ? after an empty match the next one would have to skip a char
+        let regex = RegexBuilder::new("a*")
+            .anchored(Anchored::Yes)
+            .build()
+            .unwrap();
+        let matches: Vec<_> = regex.find_iter("baa").map(|found| found.range()).collect();
+        assert_eq!(matches, vec![0..0]);
+        let matches: Vec<_> = regex.find_iter("aab").map(|found| found.range()).collect();
+        assert_eq!(matches, vec![0..2]);
+    }
+
+    #[test]
+    fn test_match_kind() {
+        let build = |match_kind| {
+            RegexBuilder::new("a|ab|abc")
+                .match_kind(match_kind)
+                .build()
+                .unwrap()
+        };
+        let first = build(MatchKind::LeftmostFirst);
+        let longest = build(MatchKind::LeftmostLongest);
+
+        assert_eq!(first.match_kind(), MatchKind::LeftmostFirst);
+        assert_eq!(first.find("xabcd").map(|found| found.range()), Some(1..2));
+        assert_eq!(longest.find("xabcd").map(|found| found.range()), Some(1..4));
+        assert_eq!(
+            Regex::new("a|ab")
+                .unwrap()
+                .find("ab")
+                .map(|found| found.as_str()),
+            Some("a")
+        );
+
+        // ? the leftmost start wins over length either way
+        let longest = RegexBuilder::new("bcde|ab")
+            .match_kind(MatchKind::LeftmostLongest)
+            .build()
+            .unwrap();
+        assert_eq!(longest.find("abcde").map(|found| found.range()), Some(0..2));
+    }
+
+    #[test]
+    fn test_match_kind_agrees_with_pikevm() {
+        use crate::pikevm::PikeVM;
+
+        for pattern in ["a|ab", "(a|ab)(c|bcd)", "(ab)*a*", "a*(ab)*b", "[a-c]*c|b"] {
+            for match_kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+                let regex = RegexBuilder::new(pattern)
+                    .match_kind(match_kind)
+                    .build()
+                    .unwrap();
+                assert!(
+                    regex.dfa().is_some(),
+                    "{} fell back to the Pike VM",
+                    pattern
+                );
+                let vm = PikeVM::new(regex.nfa());
+
+                for haystack in ["abcd", "xababab", "cabbc", "bb", ""] {
+                    let expected = vm
+                        .search(haystack, 0, Anchored::No, match_kind)
+                        .and_then(|caps| caps.get(0))
+                        .map(|found| found.range());
+                    assert_eq!(
+                        regex.find(haystack).map(|found| found.range()),
+                        expected,
+                        "{} on {:?}",
+                        pattern,
+                        haystack
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_anchors() {
+        // ? starts with, ends with and is, where a bare pattern means contains
+        let starts_with = Regex::new("^ab").unwrap();
+        assert!(starts_with.is_match("abc"));
+        assert!(!starts_with.is_match("cab"));
+
+        let ends_with = Regex::new("ab\\z").unwrap();
+        assert!(ends_with.is_match("cab"));
+        assert!(!ends_with.is_match("abc"));
+
+        let is = Regex::new("\\A(ab)*$").unwrap();
+        assert!(is.is_match("abab"));
+        assert!(is.is_match(""));
+        assert!(!is.is_match("aba"));
+
+        assert_eq!(get_matches("^a", "aaa"), vec![(0, 1)]);
+        assert_eq!(get_matches("a$", "aaa"), vec![(2, 3)]);
+        assert_eq!(get_matches("^|$", "ab"), vec![(0, 0), (2, 2)]);
+        assert_eq!(Regex::new("a^").unwrap().find("aa"), None);
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        assert_eq!(
+            get_matches("\\b[a-z]+\\b", "one, two3 four"),
+            vec![(0, 3), (10, 14)]
+        );
+        assert_eq!(
+            get_matches("\\Bo\\B", "foo ox cook"),
+            vec![(1, 2), (8, 9), (9, 10)]
+        );
+        assert_eq!(
+            get_matches("\\b", "ab c"),
+            vec![(0, 0), (2, 2), (3, 3), (4, 4)]
+        );
+        assert!(Regex::new("\\bcat\\b").unwrap().is_match("a cat."));
+        assert!(!Regex::new("\\bcat\\b").unwrap().is_match("concat"));
+
+        // ? 'é' is a word char, but not to the ASCII word boundary
+        assert_eq!(get_matches("\\b[a-z]+\\b", "café x"), vec![(6, 7)]);
+        let ascii = RegexBuilder::new("\\b[a-z]+\\b")
+            .unicode(false)
+            .build()
+            .unwrap();
+        let matches: Vec<_> = ascii
+            .find_iter("café x")
+            .map(|found| found.range())
+            .collect();
+        assert_eq!(matches, vec![0..3, 6..7]);
+    }
+
+    #[test]
+    fn test_find_uses_reverse_scan() {
+        // ? the first match to end ("c" at 2..3) is not the leftmost one
+        let regex = Regex::new("abcd|c").unwrap();
+        assert_eq!(regex.find("abcd").map(|found| found.range()), Some(0..4));
+
+        let regex = Regex::new("a*b").unwrap();
+        assert_eq!(regex.find("xxaaab").map(|found| found.range()), Some(2..6));
+        assert_eq!(get_matches("a*b", "aabab-b"), vec![(0, 3), (3, 5), (6, 7)]);
+    }
+
+    #[test]
+    fn test_find_agrees_with_pikevm() {
+        use crate::pikevm::PikeVM;
+
+        let haystacks = ["", "abcabc", "xxaab", "bacab", "ccc", "éaé", "ab c-a"];
+        for pattern in [
+            "a*",
+            "ab|b",
+            "(a|b)*c",
+            "b(a|c)*",
+            "[a-c]*c|a",
+            "é|a*",
+            "a+?",
+            "[a-c]*?c",
+            "(a|b)??b",
+            "a{1,2}?[bc]",
+            "^a*",
+            "a$|c",
+            "^(ab|b)*$",
+            "\\Ac|a\\z",
+            "a*$",
+            "$",
+            "\\b",
+            "\\Ba*\\b",
+            "\\b[a-c]+?\\b|é",
+            "a\\B|\\bc",
+        ] {
+            for match_kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+                let regex = RegexBuilder::new(pattern)
+                    .match_kind(match_kind)
+                    .build()
+                    .unwrap();
+                assert!(
+                    regex.dfa().is_some(),
+                    "{} fell back to the Pike VM",
+                    pattern
+                );
+                let vm = PikeVM::new(regex.nfa());
+
+                for haystack in haystacks {
+                    for start in (0..=haystack.len()).filter(|pos| haystack.is_char_boundary(*pos))
+                    {
+                        let expected = vm
+                            .search(haystack, start, Anchored::No, match_kind)
+                            .and_then(|caps| caps.get(0))
+                            .map(|found| found.range());
+                        assert_eq!(
+                            regex.find_at(haystack, start).map(|found| found.range()),
+                            expected,
+                            "{} on {:?} from {}",
+                            pattern,
+                            haystack,
+                            start
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_lazy() {
+        assert_eq!(
+            get_matches("<[a-z/<>]*?>", "<a><b>x</b>"),
+            vec![(0, 3), (3, 6), (7, 11)]
+        );
+        assert_eq!(get_matches("<[a-z/<>]*>", "<a><b>x</b>"), vec![(0, 11)]);
+        assert_eq!(get_matches("a+?", "aaa"), vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(get_matches("a{2,}?", "aaaaa"), vec![(0, 2), (2, 4)]);
+
+        // ? leftmost-longest has no notion of preference, laziness doesn't change it
+        let regex = RegexBuilder::new("a+?")
+            .match_kind(MatchKind::LeftmostLongest)
+            .build()
+            .unwrap();
+        assert_eq!(regex.find("aaa").map(|found| found.range()), Some(0..3));
+    }
+
+    #[test]
+    fn test_captures_lazy() {
+        let regex = Regex::new("(a+?)(a*)(b??)").unwrap();
+        let caps = regex.captures("aaab").unwrap();
+
+        assert_eq!(&caps[0], "aaa");
+        assert_eq!(&caps[1], "a");
+        assert_eq!(&caps[2], "aa");
+        assert_eq!(&caps[3], "");
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = Regex::new("(?<key>[a-z]*)=([0-9]*)").unwrap();
+        let caps = regex.captures("-- width=80;").unwrap();
+
+        assert_eq!(&caps[0], "width=80");
+        assert_eq!(caps.name("key").map(|group| group.range()), Some(3..8));
+        assert_eq!(&caps[2], "80");
+        assert!(regex.captures("none").is_none());
+    }
+
+    #[test]
+    fn test_captures_zero_repetition() {
+        let regex = Regex::new("(a)(?<x>b){0}").unwrap();
+        let caps = regex.captures("a").unwrap();
+
+        assert_eq!(caps.len(), 3);
+        assert_eq!(&caps[1], "a");
+        assert!(caps.get(2).is_none());
+        assert!(caps.name("x").is_none());
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new("([0-9][0-9]*)-([0-9][0-9]*)").unwrap();
+
+        assert_eq!(regex.replace("1-2 and 3-4", "$2-$1"), "2-1 and 3-4");
+        assert_eq!(regex.replace_all("1-2 and 3-4", "$2-$1"), "2-1 and 4-3");
+        assert_eq!(regex.replacen("1-2 3-4 5-6", 2, "($0)"), "(1-2) (3-4) 5-6");
+        assert_eq!(regex.replace_all("no digits", "x"), "no digits");
+    }
+
+    #[test]
+    fn test_replace_named_and_escaped() {
+        let regex = Regex::new("(?<amount>[0-9][0-9]*) (?<unit>[a-z][a-z]*)").unwrap();
+        let template = String::from("$$${amount}_$unit");
+
+        assert_eq!(
+            regex.replace_all("5 usd, 7 eur", &template),
+            "$5_usd, $7_eur"
+        );
+    }
+
+    #[test]
+    fn test_replace_with_closure() {
+        let regex = Regex::new("[a-z][a-z]*").unwrap();
+
+        let replaced = regex.replace_all("abc de-f", |caps: &Captures| caps[0].to_uppercase());
+        assert_eq!(replaced, "ABC DE-F");
+
+        let mut count = 0;
+        let replaced = regex.replace_all("a b c", |_: &Captures| {
+            count += 1;
+            count.to_string()
+        });
+        assert_eq!(replaced, "1 2 3");
+    }
+
+    #[test]
+    fn test_replace_no_expansion() {
+        use std::cell::Cell;
+
+        // ? counts the matches it had to expand
+        struct Counted<'c>(&'c Cell<usize>, &'static str);
+        impl Replacer for Counted<'_> {
+            fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+                self.0.set(self.0.get() + 1);
+                caps.expand(self.1, dst);
+            }
+
+            fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+                (!self.1.contains('$')).then_some(Cow::Borrowed(self.1))
+            }
+        }
+
+        let regex = Regex::new("(a)b").unwrap();
+        let count = Cell::new(0);
+        assert_eq!(regex.replace_all("ab ab", Counted(&count, "x")), "x x");
+        assert_eq!(count.get(), 0);
+
+        assert_eq!(
+            regex.replace_all("ab ab", Counted(&count, "[$1]")),
+            "[a] [a]"
+        );
+        assert_eq!(count.get(), 2);
+
+        assert_eq!("x".no_expansion(), Some(Cow::Borrowed("x")));
+        assert_eq!("$1".no_expansion(), None);
+    }
+
+    #[test]
+    fn test_captures_of_falls_back_to_the_match() {
+        let regex = Regex::new("(a)").unwrap();
+
+        let caps = regex.captures_of("ba", Match::new("ba", 1, 2));
+        assert_eq!((&caps[0], &caps[1]), ("a", "a"));
+
+        // ? a span the Pike VM can't find again keeps just the whole match
+        let caps = regex.captures_of("ba", Match::new("ba", 0, 1));
+        assert_eq!(caps.len(), 2);
+        assert_eq!(&caps[0], "b");
+        assert!(caps.get(1).is_none());
+    }
+
+    #[test]
+    fn test_replace_empty_matches() {
+        let regex = Regex::new("x*").unwrap();
+
+        assert_eq!(regex.replace_all("abc", "-"), "-a-b-c-");
+        assert_eq!(regex.replace_all("axxb", "-"), "-a-b-");
+    }
+
+    fn get_split(pattern: &str, haystack: &str) -> Vec<String> {
+        let regex = Regex::new(pattern).unwrap();
+        regex.split(haystack).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(get_split(", *", "a, b,c"), vec!["a", "b", "c"]);
+        assert_eq!(get_split(",", ",a,,b,"), vec!["", "a", "", "b", ""]);
+        assert_eq!(get_split(",", "abc"), vec!["abc"]);
+        assert_eq!(get_split(",", ""), vec![""]);
+    }
+
+    #[test]
+    fn test_split_empty_matches() {
+        assert_eq!(get_split("", "abc"), vec!["", "a", "b", "c", ""]);
+        assert_eq!(get_split("x*", "axxbé"), vec!["", "a", "b", "é", ""]);
+    }
+
+    #[test]
+    fn test_splitn() {
+        let regex = Regex::new(",").unwrap();
+        let get_splitn = |haystack, limit| regex.splitn(haystack, limit).collect::<Vec<_>>();
+
+        assert_eq!(get_splitn("a,b,c", 2), vec!["a", "b,c"]);
+        assert_eq!(get_splitn("a,b,c", 3), vec!["a", "b", "c"]);
+        assert_eq!(get_splitn("a,b,c", 5), vec!["a", "b", "c"]);
+        assert_eq!(get_splitn("a,b,c", 1), vec!["a,b,c"]);
+        assert_eq!(get_splitn("a,b,c", 0), Vec::<&str>::new());
+        assert_eq!(get_splitn("a,b,", 3), vec!["a", "b", ""]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches() {
+        assert_eq!(get_matches("a*", "ab"), vec![(0, 1), (2, 2)]);
+        assert_eq!(get_matches("a*", "baa"), vec![(0, 0), (1, 3)]);
+        assert_eq!(get_matches("", "é"), vec![(0, 0), (2, 2)]);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::captures::Captures;
+use std::borrow::Cow;
+
+// ? What `Regex::replace` writes in place of each match. A template string is
+// ? expanded with `Captures::expand`, a closure gets the captures and returns
+// ? the text itself.
+// ? `no_expansion` gives the text when it is the same for every match, then no
+// ? captures are worked out at all.
+pub trait Replacer {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        None
+    }
+}
+
+// ? a template without '$' has nothing to expand
+fn literal(template: &str) -> Option<Cow<'_, str>> {
+    (!template.contains('$')).then_some(Cow::Borrowed(template))
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        literal(self)
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        literal(self)
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str((*self)(caps).as_ref());
+    }
+}
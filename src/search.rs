@@ -0,0 +1,18 @@
+// ? Anchored::Yes only reports matches starting exactly at the search start,
+// ? Anchored::No behaves as if the pattern had an implicit lazy `.*?` prefix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchored {
+    Yes,
+    #[default]
+    No,
+}
+
+// ? Which match wins among those starting at the leftmost position.
+// ? LeftmostFirst is Perl: the one the pattern prefers, `a|ab` on "ab" is "a".
+// ? LeftmostLongest is POSIX: the longest one, `a|ab` on "ab" is "ab".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchKind {
+    #[default]
+    LeftmostFirst,
+    LeftmostLongest,
+}
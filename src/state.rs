@@ -1,206 +1,324 @@
-use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
-
-use uuid::Uuid;
+use crate::look::Look;
+use std::fmt;
 
 pub const EPSILON: &str = "ε";
 pub const EPSILON_STAR: &str = "ε*";
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct State {
-    pub accepting: bool,
-    pub transition_map: HashMap<String, Vec<Rc<RefCell<State>>>>,
-    pub label: Uuid,
+
+// ? index of a state inside the arena (NFA::states) that owns it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StateId(pub u32);
+
+impl StateId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
 }
 
-impl State {
-    pub fn new(is_accepting: bool) -> State {
-        State {
-            accepting: is_accepting,
-            transition_map: HashMap::new(),
-            label: Uuid::new_v4(),
-        }
+impl fmt::Display for StateId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "q{}", self.0)
     }
+}
+
+// ? inclusive range of chars, the label of a class transition
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CharRange {
+    pub start: char,
+    pub end: char,
+}
 
-    pub fn add_transition_for_symbol(&mut self, symbol: &str, new_state: Rc<RefCell<State>>) {
-        self.transition_map
-            .entry(symbol.to_string())
-            .or_insert_with(Vec::new)
-            .push(new_state);
+impl CharRange {
+    // ? every char, surrogates aren't chars so there is no gap to worry about
+    pub const FULL: CharRange = CharRange {
+        start: '\0',
+        end: char::MAX,
+    };
+
+    pub fn new(start: char, end: char) -> CharRange {
+        CharRange { start, end }
     }
 
-    pub fn get_transition_for_symbol(&self, symbol: &str) -> Vec<Rc<RefCell<State>>> {
-        match self.transition_map.get(symbol) {
-            Some(states) => states.clone(),
-            None => Vec::new(),
-        }
+    pub fn single(symbol: char) -> CharRange {
+        CharRange::new(symbol, symbol)
     }
 
-    pub fn get_all_transition_symbols(&self) -> Vec<String> {
-        self.transition_map.keys().cloned().collect()
+    pub fn contains(&self, symbol: char) -> bool {
+        self.start <= symbol && symbol <= self.end
     }
 
-    // ? DFA Traversal
-
-    pub fn count_unique_transitions(
-        &self,
-    ) -> (
-        u64,
-        HashSet<String>,
-        HashSet<Uuid>,
-        HashMap<Uuid, State>,
-        Uuid,
-    ) {
-        let mut stack: Vec<Rc<RefCell<State>>> = Vec::new();
-        let mut is_visited: HashMap<Uuid, bool> = HashMap::new();
-        let mut count: u64 = 0;
-
-        let mut all_transition_symbols: HashSet<String> = HashSet::new();
-        let mut all_uuid: HashSet<Uuid> = HashSet::new();
-        let mut map: HashMap<Uuid, State> = HashMap::new();
-
-        stack.push(Rc::new(RefCell::new(self.clone())));
-
-        let mut accepting_state_uuid: Uuid = self.label;
-
-        while let Some(curr_state) = stack.pop() {
-            let curr_state_ref = curr_state.borrow();
-            let curr_label = curr_state_ref.label;
-            all_uuid.insert(curr_label);
-            map.insert(curr_label, curr_state_ref.clone());
-
-            if curr_state_ref.accepting {
-                accepting_state_uuid = curr_label;
-            }
-            if is_visited.get(&curr_state_ref.label) == Some(&true) {
-                continue;
+    // ? sorted, with overlapping and touching ranges merged
+    pub fn canonicalize(ranges: &[CharRange]) -> Vec<CharRange> {
+        let mut sorted: Vec<CharRange> = ranges.to_vec();
+        sorted.sort();
+
+        let mut merged: Vec<CharRange> = Vec::new();
+        for range in sorted {
+            match merged.last_mut() {
+                Some(last) if next_char(last.end).is_some_and(|next| range.start <= next) => {
+                    last.end = last.end.max(range.end);
+                }
+                _ => merged.push(range),
             }
+        }
+        merged
+    }
+
+    // ? every char not in any of the ranges
+    pub fn complement(ranges: &[CharRange]) -> Vec<CharRange> {
+        let mut gaps: Vec<CharRange> = Vec::new();
+        let mut gap_start = Some('\0');
 
-            count += 1;
-            is_visited.insert(curr_state_ref.label.clone(), true);
-
-            let all_transition = curr_state_ref.get_all_transition_symbols();
-            for next_transition in all_transition {
-                all_transition_symbols.insert(next_transition.clone());
-                let next_states = curr_state_ref.get_transition_for_symbol(&next_transition);
-                for next_state in next_states {
-                    if is_visited.get(&next_state.borrow().label) != Some(&true) {
-                        stack.push(next_state.clone());
-                    }
+        for range in CharRange::canonicalize(ranges) {
+            if let Some(start) = gap_start {
+                if start < range.start {
+                    gaps.push(CharRange::new(start, prev_char(range.start).unwrap()));
                 }
             }
+            gap_start = next_char(range.end);
         }
+        if let Some(start) = gap_start {
+            gaps.push(CharRange::new(start, char::MAX));
+        }
+        gaps
+    }
 
-        return (
-            count,
-            all_transition_symbols,
-            all_uuid,
-            map,
-            accepting_state_uuid,
-        );
+    // ? Cuts the ranges at every boundary of any of them, so each piece is either
+    // ? inside or outside every range. Pieces no range covers are left out.
+    pub fn disjoint(ranges: &[CharRange]) -> Vec<CharRange> {
+        let mut boundaries: Vec<char> = ranges
+            .iter()
+            .flat_map(|range| [Some(range.start), next_char(range.end)])
+            .flatten()
+            .collect();
+        boundaries.sort();
+        boundaries.dedup();
+
+        let mut pieces: Vec<CharRange> = Vec::new();
+        for (index, start) in boundaries.iter().enumerate() {
+            let end = match boundaries.get(index + 1) {
+                Some(next) => prev_char(*next).unwrap(),
+                None => char::MAX,
+            };
+            if ranges.iter().any(|range| range.contains(*start)) {
+                pieces.push(CharRange::new(*start, end));
+            }
+        }
+        pieces
     }
+}
 
-    // EPSILON
-    pub fn epslion_closure(&self) -> Vec<State> {
-        let mut epsilon_vector: Vec<State> = Vec::new();
+impl fmt::Display for CharRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start.escape_debug())
+        } else {
+            write!(
+                f,
+                "{}-{}",
+                self.start.escape_debug(),
+                self.end.escape_debug()
+            )
+        }
+    }
+}
 
-        epsilon_vector.push(self.clone());
+// ? the neighbouring chars, skipping the surrogate gap
+pub fn next_char(symbol: char) -> Option<char> {
+    match symbol {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(symbol as u32 + 1),
+    }
+}
 
-        let all_symbols = self.get_all_transition_symbols();
+pub fn prev_char(symbol: char) -> Option<char> {
+    match symbol {
+        '\0' => None,
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => char::from_u32(symbol as u32 - 1),
+    }
+}
 
-        for symbol in all_symbols.iter() {
-            let states = self.get_transition_for_symbol(symbol);
+// ? what a transition is taken on, ε, any char of the range, or nothing as long
+// ? as the assertion holds. Ranges sort before ε so tables list ε last, as they
+// ? did when symbols were strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Symbol {
+    Range(CharRange),
+    Look(Look),
+    Epsilon,
+}
 
-            for state in states.iter() {
-                let curr_state = state.borrow().clone();
-                epsilon_vector.push(curr_state);
-            }
+impl Symbol {
+    pub fn char(symbol: char) -> Symbol {
+        Symbol::Range(CharRange::single(symbol))
+    }
+
+    // ? the symbol in an NFA that reads the haystack backwards
+    pub fn reversed(self) -> Symbol {
+        match self {
+            Symbol::Look(look) => Symbol::Look(look.reversed()),
+            symbol => symbol,
         }
-        return epsilon_vector;
     }
+}
 
-    pub fn test(&self, _string: &str) -> bool {
-        return self.test_helper(_string, HashMap::new());
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Range(range) => write!(f, "{}", range),
+            Symbol::Look(look) => write!(f, "{}", look),
+            Symbol::Epsilon => write!(f, "{}", EPSILON),
+        }
     }
+}
 
-    pub fn test_helper(&self, _string: &str, mut is_visited: HashMap<Uuid, bool>) -> bool {
-        let label = self.label;
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    pub accepting: bool,
+    // ? sorted and disjoint, so the targets of a char are found by binary search.
+    // ? Targets keep the order they were added in, that is thread priority.
+    pub transitions: Vec<(CharRange, Vec<StateId>)>,
+    pub epsilon_transitions: Vec<StateId>,
+    // ? ε moves that are only taken where the assertion holds
+    pub look_transitions: Vec<(Look, Vec<StateId>)>,
+    // ? capture slot that records the input position whenever a thread enters this state
+    pub capture: Option<usize>,
+}
 
-        let is_curr_visited = is_visited.get(&label);
+impl State {
+    pub fn new(is_accepting: bool) -> State {
+        State {
+            accepting: is_accepting,
+            transitions: Vec::new(),
+            epsilon_transitions: Vec::new(),
+            look_transitions: Vec::new(),
+            capture: None,
+        }
+    }
 
-        match is_curr_visited {
-            Some(val) => {
-                if *val == true {
-                    return false;
+    // ? a range overlapping existing ones is cut at their boundaries, then
+    // ? neighbouring pieces with the same targets are merged back together
+    pub fn add_transition(&mut self, symbol: Symbol, new_state: StateId) {
+        let range = match symbol {
+            Symbol::Range(range) => range,
+            Symbol::Epsilon => {
+                self.epsilon_transitions.push(new_state);
+                return;
+            }
+            Symbol::Look(look) => {
+                match self
+                    .look_transitions
+                    .iter_mut()
+                    .find(|(other, _)| *other == look)
+                {
+                    Some((_, next_states)) => next_states.push(new_state),
+                    None => self.look_transitions.push((look, vec![new_state])),
                 }
+                return;
             }
-            None => {}
-        }
+        };
 
-        is_visited.insert(label, true);
+        let mut ranges: Vec<CharRange> = self.get_transition_ranges().collect();
+        ranges.push(range);
 
-        if _string.is_empty() {
-            if self.accepting {
-                return true;
+        let mut transitions: Vec<(CharRange, Vec<StateId>)> = Vec::new();
+        for piece in CharRange::disjoint(&ranges) {
+            let mut next_states = self.get_transitions_for_char(piece.start).to_vec();
+            if range.contains(piece.start) {
+                next_states.push(new_state);
             }
 
-            let epsilon_transitions = self.get_transition_for_symbol(EPSILON);
-
-            for next_state in epsilon_transitions.iter() {
-                if next_state.borrow().test_helper("", is_visited.clone()) {
-                    return true;
+            match transitions.last_mut() {
+                Some((last, last_states))
+                    if next_char(last.end) == Some(piece.start) && *last_states == next_states =>
+                {
+                    last.end = piece.end;
                 }
+                _ => transitions.push((piece, next_states)),
             }
-            return false;
         }
+        self.transitions = transitions;
+    }
 
-        let first_char = _string.chars().next().unwrap().to_string();
-        let rest_of_string = &_string[first_char.len()..];
-
-        let symbol_transitions = self.get_transition_for_symbol(&first_char);
+    pub fn get_transitions_for_char(&self, symbol: char) -> &[StateId] {
+        let index = self
+            .transitions
+            .partition_point(|(range, _)| range.end < symbol);
+        match self.transitions.get(index) {
+            Some((range, next_states)) if range.start <= symbol => next_states,
+            _ => &[],
+        }
+    }
 
-        for next_state in symbol_transitions.iter() {
-            if next_state
-                .borrow()
-                .test_helper(rest_of_string, is_visited.clone())
-            {
-                return true;
-            }
+    // ? the states a symbol leads to, a range has to be one of the state's own
+    pub fn get_transitions(&self, symbol: Symbol) -> &[StateId] {
+        match symbol {
+            Symbol::Epsilon => &self.epsilon_transitions,
+            Symbol::Look(look) => self
+                .look_transitions
+                .iter()
+                .find(|(other, _)| *other == look)
+                .map_or(&[], |(_, next_states)| next_states),
+            Symbol::Range(range) => self
+                .transitions
+                .iter()
+                .find(|(other, _)| *other == range)
+                .map_or(&[], |(_, next_states)| next_states),
         }
+    }
 
-        let eplision_transition_for_next_state = self.get_transition_for_symbol(EPSILON);
+    pub fn get_transition_ranges(&self) -> impl Iterator<Item = CharRange> + '_ {
+        self.transitions.iter().map(|(range, _)| *range)
+    }
 
-        for next_state in eplision_transition_for_next_state.iter() {
-            if next_state.borrow().test_helper(_string, is_visited.clone()) {
-                return true;
-            }
+    // ? the ranges in order, the assertions, then ε if the state has any ε moves
+    pub fn get_all_transition_symbols(&self) -> Vec<Symbol> {
+        let epsilon = (!self.epsilon_transitions.is_empty()).then_some(Symbol::Epsilon);
+        let looks = self
+            .look_transitions
+            .iter()
+            .map(|(look, _)| Symbol::Look(*look));
+        self.get_transition_ranges()
+            .map(Symbol::Range)
+            .chain(looks)
+            .chain(epsilon)
+            .collect()
+    }
+
+    // ? used when an NFA is moved into another arena, every target shifts with it
+    pub fn shift_transitions(&mut self, offset: u32) {
+        let next_states = self
+            .transitions
+            .iter_mut()
+            .flat_map(|(_, next_states)| next_states.iter_mut())
+            .chain(self.epsilon_transitions.iter_mut())
+            .chain(
+                self.look_transitions
+                    .iter_mut()
+                    .flat_map(|(_, next_states)| next_states.iter_mut()),
+            );
+        for next_state in next_states {
+            next_state.0 += offset;
         }
-        return false;
     }
 }
 
 #[cfg(test)]
 mod test {
 
+    use crate::look::Look;
     use crate::nfa::NFA;
 
-    use crate::state::State;
-    use std::cell::RefCell;
-    use std::rc::Rc;
+    use crate::state::{CharRange, State, StateId, Symbol};
 
     use super::EPSILON;
     #[test]
     fn test_count_unique_transitions() {
-        let mut nfa_1 = NFA::char("a");
-        let mut nfa_2 = NFA::char("b");
+        let nfa_1 = NFA::char('a');
+        let nfa_2 = NFA::char('b');
 
-        let final_concat_nfa = NFA::concat_pair(&mut nfa_1, &mut nfa_2);
-        let res = final_concat_nfa
-            .in_state
-            .borrow()
-            .count_unique_transitions();
+        let final_concat_nfa = NFA::concat_pair(nfa_1, nfa_2);
+        let res = final_concat_nfa.count_unique_transitions();
 
         let concat_count = res.0;
         assert_eq!(concat_count, 4);
@@ -208,64 +326,59 @@ mod test {
         assert_eq!(res.2.len(), concat_count.try_into().unwrap());
 
         let map = res.1;
-        assert!(map.contains("a"));
-        assert!(map.contains("b"));
-        assert!(map.contains(EPSILON));
-        assert!(!map.contains("suii"));
+        assert!(map.contains(&Symbol::char('a')));
+        assert!(map.contains(&Symbol::char('b')));
+        assert!(map.contains(&Symbol::Epsilon));
+        assert!(!map.contains(&Symbol::char('s')));
 
-        let mut nfa_3 = NFA::char("c");
-        let mut nfa_4 = NFA::char("d");
+        let nfa_3 = NFA::char('c');
+        let nfa_4 = NFA::char('d');
 
-        let or_nfa = NFA::or_pair(&mut nfa_3, &mut nfa_4);
-        let or_pair_count = or_nfa.in_state.borrow().count_unique_transitions();
+        let or_nfa = NFA::or_pair(nfa_3, nfa_4);
+        let or_pair_count = or_nfa.count_unique_transitions();
 
         let map = or_pair_count.1;
         assert_eq!(or_pair_count.0, 6);
-        assert!(map.contains("c"));
-        assert!(map.contains("d"));
-        assert!(map.contains(EPSILON));
+        assert!(map.contains(&Symbol::char('c')));
+        assert!(map.contains(&Symbol::char('d')));
+        assert!(map.contains(&Symbol::Epsilon));
     }
 
     #[test]
     fn test_get_all_transition_symbols() {
-        let s1 = Rc::new(RefCell::new(State::new(false)));
-        let s2 = Rc::new(RefCell::new(State::new(true)));
-
-        s1.borrow_mut()
-            .add_transition_for_symbol(EPSILON, s2.clone());
-        s1.borrow_mut().add_transition_for_symbol("a", s2.clone());
-
-        s1.borrow_mut().add_transition_for_symbol("b", s2.clone());
-        s1.borrow_mut().add_transition_for_symbol("c", s2.clone());
-        s1.borrow_mut().add_transition_for_symbol("j", s2.clone());
-        s1.borrow_mut().add_transition_for_symbol("d", s2.clone());
-
-        let mut sui = s1.borrow().get_all_transition_symbols();
-        sui.sort();
-
-        assert_eq!(sui.len(), 6);
-        assert_eq!(sui[0], "a");
-        assert_eq!(sui[1], "b");
-        assert_eq!(sui[2], "c");
-        assert_eq!(sui[3], "d");
-        assert_eq!(sui[4], "j");
-        assert_eq!(sui[5], EPSILON);
+        let mut s1 = State::new(false);
+        let s2 = StateId(1);
+
+        s1.add_transition(Symbol::Epsilon, s2);
+        s1.add_transition(Symbol::char('a'), s2);
+
+        s1.add_transition(Symbol::char('b'), s2);
+        s1.add_transition(Symbol::char('c'), s2);
+        s1.add_transition(Symbol::char('j'), s2);
+        s1.add_transition(Symbol::char('d'), s2);
+
+        let sui = s1.get_all_transition_symbols();
+
+        // ? a, b, c and d touch and lead to the same state, so they share one range
+        assert_eq!(sui.len(), 3);
+        assert_eq!(sui[0], Symbol::Range(CharRange::new('a', 'd')));
+        assert_eq!(sui[1], Symbol::char('j'));
+        assert_eq!(sui[2], Symbol::Epsilon);
+        assert_eq!(sui[2].to_string(), EPSILON);
     }
 
     #[test]
     fn test_add_and_get_transition() {
-        let s1 = Rc::new(RefCell::new(State::new(false)));
-        let s2 = Rc::new(RefCell::new(State::new(true)));
+        let mut states = [State::new(false), State::new(true)];
 
-        s1.borrow_mut()
-            .add_transition_for_symbol(EPSILON, s2.clone());
-        let transition_table_for_epsilon = s1.borrow().get_transition_for_symbol(EPSILON);
+        states[0].add_transition(Symbol::Epsilon, StateId(1));
+        let transition_table_for_epsilon = states[0].get_transitions(Symbol::Epsilon);
 
-        let first_state = transition_table_for_epsilon.get(0);
+        let first_state = transition_table_for_epsilon.first();
         match first_state {
             Some(state) => {
-                assert_eq!(*state.borrow(), *s2.borrow());
-                assert_eq!(s2.borrow().accepting, true);
+                assert_eq!(states[state.index()], states[1]);
+                assert!(states[state.index()].accepting);
             }
             None => {
                 panic!("No state found in transition table");
@@ -275,36 +388,32 @@ mod test {
 
     #[test]
     fn test_multiple_transitions() {
-        let s1 = Rc::new(RefCell::new(State::new(false)));
-        let s2 = Rc::new(RefCell::new(State::new(false)));
-        let s3 = Rc::new(RefCell::new(State::new(true)));
+        let mut states = [State::new(false), State::new(false), State::new(true)];
 
-        s1.borrow_mut()
-            .add_transition_for_symbol(EPSILON, s2.clone());
+        states[0].add_transition(Symbol::Epsilon, StateId(1));
 
-        let transition_table_for_epsilon = s1.borrow().get_transition_for_symbol(EPSILON);
+        let transition_table_for_epsilon = states[0].get_transitions(Symbol::Epsilon);
         assert_eq!(transition_table_for_epsilon.len(), 1);
 
-        let first_state = transition_table_for_epsilon.get(0);
+        let first_state = transition_table_for_epsilon.first();
         match first_state {
             Some(state) => {
-                assert_eq!(state.borrow().accepting, false);
+                assert!(!states[state.index()].accepting);
             }
             None => {
                 panic!("No state found in transition table");
             }
         }
 
-        s2.borrow_mut()
-            .add_transition_for_symbol(EPSILON, s3.clone());
+        states[1].add_transition(Symbol::Epsilon, StateId(2));
 
-        let transition_table_for_s2_epsilon = s2.borrow().get_transition_for_symbol(EPSILON);
+        let transition_table_for_s2_epsilon = states[1].get_transitions(Symbol::Epsilon);
         assert_eq!(transition_table_for_s2_epsilon.len(), 1);
 
-        let second_state = transition_table_for_s2_epsilon.get(0);
+        let second_state = transition_table_for_s2_epsilon.first();
         match second_state {
             Some(state) => {
-                assert_eq!(state.borrow().accepting, true);
+                assert!(states[state.index()].accepting);
             }
             None => {
                 panic!("No state found in transition table");
@@ -312,11 +421,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_shift_transitions() {
+        let mut state = State::new(false);
+        state.add_transition(Symbol::char('a'), StateId(0));
+        state.add_transition(Symbol::Epsilon, StateId(3));
+        state.add_transition(Symbol::Look(Look::Start), StateId(1));
+
+        state.shift_transitions(4);
+
+        assert_eq!(state.get_transitions_for_char('a'), vec![StateId(4)]);
+        assert_eq!(state.get_transitions(Symbol::Epsilon), vec![StateId(7)]);
+        assert_eq!(
+            state.get_transitions(Symbol::Look(Look::Start)),
+            vec![StateId(5)]
+        );
+        assert!(state.get_transitions(Symbol::Look(Look::End)).is_empty());
+    }
+
     #[test]
     fn test_regex_concat() {
-        let first_nfa = NFA::char("a");
-        let second_nfa = NFA::char("b");
-        let final_nfa = NFA::concat(&first_nfa, &[second_nfa]);
+        let first_nfa = NFA::char('a');
+        let second_nfa = NFA::char('b');
+        let final_nfa = NFA::concat(first_nfa, vec![second_nfa]);
 
         let result_1 = final_nfa.test("ab");
         let result_2 = final_nfa.test("ac");
@@ -324,25 +451,107 @@ mod test {
         let result_4 = final_nfa.test(" ab");
         let result_5 = final_nfa.test("ab ");
 
-        assert_eq!(result_1, true);
-        assert_eq!(result_2, false);
-        assert_eq!(result_3, false);
-        assert_eq!(result_4, false);
-        assert_eq!(result_5, false);
+        assert!(result_1);
+        assert!(!result_2);
+        assert!(!result_3);
+        assert!(!result_4);
+        assert!(!result_5);
     }
 
     #[test]
 
     fn test_epslion_closure() {
-        let mut first_nfa = NFA::char("a");
-        let mut second_nfa = NFA::char("b");
-        let final_nfa = NFA::or_pair(&mut first_nfa, &mut second_nfa);
+        let first_nfa = NFA::char('a');
+        let second_nfa = NFA::char('b');
+        let final_nfa = NFA::or_pair(first_nfa, second_nfa);
 
-        let epsilon_closure = final_nfa.in_state.borrow().epslion_closure();
+        let epsilon_closure = final_nfa.epslion_closure(final_nfa.in_state);
 
+        // ? or_pair keeps its own in/out first, then the first and second machines
         assert_eq!(epsilon_closure.len(), 3);
-        assert_eq!(epsilon_closure[0].label, final_nfa.in_state.borrow().label);
-        assert_eq!(epsilon_closure[1].label, first_nfa.in_state.borrow().label);
-        assert_eq!(epsilon_closure[2].label, second_nfa.in_state.borrow().label);
+        assert_eq!(epsilon_closure[0], final_nfa.in_state);
+        assert_eq!(epsilon_closure[1], StateId(2));
+        assert_eq!(epsilon_closure[2], StateId(4));
+    }
+
+    #[test]
+    fn test_range_transitions() {
+        let mut state = State::new(false);
+        state.add_transition(Symbol::char('m'), StateId(1));
+        state.add_transition(Symbol::Range(CharRange::new('a', 'z')), StateId(2));
+        state.add_transition(Symbol::Epsilon, StateId(0));
+        state.shift_transitions(1);
+
+        // ? the overlap is cut out of the class so the ranges stay disjoint
+        assert_eq!(
+            state.get_transition_ranges().collect::<Vec<_>>(),
+            vec![
+                CharRange::new('a', 'l'),
+                CharRange::single('m'),
+                CharRange::new('n', 'z')
+            ]
+        );
+        assert_eq!(
+            state.get_transitions_for_char('m'),
+            [StateId(2), StateId(3)]
+        );
+        assert_eq!(state.get_transitions_for_char('q'), [StateId(3)]);
+        assert_eq!(state.get_transitions_for_char('a'), [StateId(3)]);
+        assert!(state.get_transitions_for_char('A').is_empty());
+        assert!(state.get_transitions_for_char('{').is_empty());
+        assert_eq!(state.get_transitions(Symbol::Epsilon), [StateId(1)]);
+        assert_eq!(
+            state.get_transitions(Symbol::Range(CharRange::new('n', 'z'))),
+            [StateId(3)]
+        );
+    }
+
+    #[test]
+    fn test_char_range_canonicalize_and_complement() {
+        let ranges = [
+            CharRange::new('x', 'z'),
+            CharRange::new('a', 'c'),
+            CharRange::new('b', 'f'),
+            CharRange::single('g'),
+        ];
+        assert_eq!(
+            CharRange::canonicalize(&ranges),
+            vec![CharRange::new('a', 'g'), CharRange::new('x', 'z')]
+        );
+
+        assert_eq!(
+            CharRange::complement(&[CharRange::new('b', 'y')]),
+            vec![CharRange::new('\0', 'a'), CharRange::new('z', char::MAX)]
+        );
+        assert_eq!(CharRange::complement(&[CharRange::FULL]), vec![]);
+        assert_eq!(CharRange::complement(&[]), vec![CharRange::FULL]);
+        // ? surrogates are not chars, the two sides of the gap are neighbours
+        assert_eq!(
+            CharRange::complement(&[CharRange::new('\0', '\u{D7FF}')]),
+            vec![CharRange::new('\u{E000}', char::MAX)]
+        );
+    }
+
+    #[test]
+    fn test_char_range_disjoint() {
+        let ranges = [
+            CharRange::new('a', 'm'),
+            CharRange::new('f', 'z'),
+            CharRange::single('5'),
+        ];
+
+        assert_eq!(
+            CharRange::disjoint(&ranges),
+            vec![
+                CharRange::single('5'),
+                CharRange::new('a', 'e'),
+                CharRange::new('f', 'm'),
+                CharRange::new('n', 'z'),
+            ]
+        );
+        assert_eq!(
+            CharRange::disjoint(&[CharRange::FULL]),
+            vec![CharRange::FULL]
+        );
     }
 }